@@ -1,21 +1,39 @@
 [[block]]
 struct MandelbrotParameters {
-	range_min: f32;
-	range_max: f32;
+	range_x:   f32;
+	range_y:   f32;
 	pos_x:     f32;
 	pos_y:     f32;
     max_iter:  u32;
     width:     u32;
     height:    u32;
+    bailout:   f32;
+    julia:     u32;
+    julia_x:   f32;
+    julia_y:   f32;
+    formula:   u32;
+    degree:    f32;
+    // Row major rotation and skew matrix
+    m00:       f32;
+    m01:       f32;
+    m10:       f32;
+    m11:       f32;
+};
+
+// Nothing here depends on the coloring, it's applied on the cpu
+struct Field {
+    iter:     u32;
+    value:    f32;
+    distance: f32;
 };
 
 [[block]]
-struct Pixels {
-    data: array<array<u32,3>, PIXEL_COUNT >;
+struct Fields {
+    data: array<Field, PIXEL_COUNT >;
 };
 
 [[group(0), binding(0)]]
-var<storage, read_write> v_pixels: Pixels;
+var<storage, read_write> v_fields: Fields;
 
 [[group(0), binding(1)]]
 var<storage, read_write> v_params: MandelbrotParameters;
@@ -24,40 +42,273 @@ fn map(val: f32, i_min: f32, i_max: f32, o_min: f32, o_max: f32) -> f32 {
 	return (val-i_min)/(i_max-i_min) * (o_max-o_min) + o_min;
 }
 
-fn compute(index: u32) -> array<u32,3>{
+fn mul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(a.x*b.x - a.y*b.y, a.x*b.y + a.y*b.x);
+}
+
+fn real_pow(z: vec2<f32>, p: f32) -> vec2<f32> {
+    if (z.x == 0.0f32 && z.y == 0.0f32) {
+        return z;
+    }
+    var r:     f32 = pow(dot(z, z), p/2.0f32);
+    var theta: f32 = atan2(z.y, z.x)*p;
+    return vec2<f32>(r*cos(theta), r*sin(theta));
+}
+
+// Complex number and its derivative, user formulas are evaluated on these
+struct Dual {
+    v: vec2<f32>;
+    d: vec2<f32>;
+};
+
+fn c_div(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(a.x*b.x + a.y*b.y, a.y*b.x - a.x*b.y) / dot(b, b);
+}
+
+fn c_exp(a: vec2<f32>) -> vec2<f32> {
+    return exp(a.x)*vec2<f32>(cos(a.y), sin(a.y));
+}
+
+fn c_log(a: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(log(dot(a, a)) / 2.0f32, atan2(a.y, a.x));
+}
+
+fn c_sin(a: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(sin(a.x)*cosh(a.y), cos(a.x)*sinh(a.y));
+}
+
+fn c_cos(a: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(cos(a.x)*cosh(a.y), -sin(a.x)*sinh(a.y));
+}
+
+fn dual_constant(re: f32, im: f32) -> Dual {
+    return Dual(vec2<f32>(re, im), vec2<f32>(0.0f32, 0.0f32));
+}
+
+fn dual_add(a: Dual, b: Dual) -> Dual {
+    return Dual(a.v + b.v, a.d + b.d);
+}
+
+fn dual_sub(a: Dual, b: Dual) -> Dual {
+    return Dual(a.v - b.v, a.d - b.d);
+}
+
+fn dual_neg(a: Dual) -> Dual {
+    return Dual(-a.v, -a.d);
+}
+
+fn dual_mul(a: Dual, b: Dual) -> Dual {
+    return Dual(mul(a.v, b.v), mul(a.d, b.v) + mul(a.v, b.d));
+}
+
+fn dual_div(a: Dual, b: Dual) -> Dual {
+    return Dual(c_div(a.v, b.v), c_div(mul(a.d, b.v) - mul(a.v, b.d), mul(b.v, b.v)));
+}
+
+fn dual_powi(a: Dual, n: i32) -> Dual {
+    var v:    vec2<f32> = vec2<f32>(1.0f32, 0.0f32);
+    var prev: vec2<f32> = vec2<f32>(1.0f32, 0.0f32);
+    var i: i32 = 0i32;
+    loop {
+        if (i >= abs(n)) {
+            break;
+        }
+        prev = v;
+        v = mul(v, a.v);
+        i = i + 1i32;
+    }
+    var result: Dual = Dual(v, mul(f32(abs(n))*prev, a.d));
+    if (n < 0i32) {
+        return dual_div(dual_constant(1.0f32, 0.0f32), result);
+    }
+    return result;
+}
+
+fn dual_exp(a: Dual) -> Dual {
+    var v: vec2<f32> = c_exp(a.v);
+    return Dual(v, mul(v, a.d));
+}
+
+fn dual_log(a: Dual) -> Dual {
+    return Dual(c_log(a.v), c_div(a.d, a.v));
+}
+
+fn dual_pow(a: Dual, b: Dual) -> Dual {
+    if (a.v.x == 0.0f32 && a.v.y == 0.0f32) {
+        return dual_constant(0.0f32, 0.0f32);
+    }
+    return dual_exp(dual_mul(dual_log(a), b));
+}
+
+fn dual_sin(a: Dual) -> Dual {
+    return Dual(c_sin(a.v), mul(c_cos(a.v), a.d));
+}
+
+fn dual_cos(a: Dual) -> Dual {
+    return Dual(c_cos(a.v), -mul(c_sin(a.v), a.d));
+}
+
+fn dual_abs(a: Dual) -> Dual {
+    return Dual(abs(a.v), sign(a.v)*a.d);
+}
+
+fn dual_conj(a: Dual) -> Dual {
+    return Dual(vec2<f32>(a.v.x, -a.v.y), vec2<f32>(a.d.x, -a.d.y));
+}
+
+// The user's formula, generated from an Expression
+fn custom_formula(z: Dual, c: Dual) -> Dual {
+    return CUSTOM_FORMULA ;
+}
+
+// z -> f(z) without c, the formula ids match Formula::shader_id
+fn formula_step(z: vec2<f32>) -> vec2<f32> {
+    var f: u32 = v_params.formula;
+    if (f == 0u32) {
+        var w: vec2<f32> = z;
+        var n: u32 = 1u32;
+        loop {
+            if (n >= u32(v_params.degree)) {
+                break;
+            }
+            w = mul(w, z);
+            n = n + 1u32;
+        }
+        return w;
+    }
+    if (f == 1u32) {
+        return real_pow(z, v_params.degree);
+    }
+    if (f == 2u32) {
+        return mul(abs(z), abs(z));
+    }
+    if (f == 3u32) {
+        var w: vec2<f32> = vec2<f32>(z.x, -z.y);
+        return mul(w, w);
+    }
+    var w: vec2<f32> = mul(z, z);
+    if (f == 4u32) {
+        return vec2<f32>(abs(w.x), w.y);
+    }
+    return abs(w);
+}
+
+// Derivative of formula_step applied to d, along the real axis for the formulas which fold
+fn formula_derivative(z: vec2<f32>, d: vec2<f32>) -> vec2<f32> {
+    var f: u32 = v_params.formula;
+    if (f == 0u32) {
+        var w: vec2<f32> = vec2<f32>(v_params.degree, 0.0f32);
+        var n: u32 = 1u32;
+        loop {
+            if (n >= u32(v_params.degree)) {
+                break;
+            }
+            w = mul(w, z);
+            n = n + 1u32;
+        }
+        return mul(w, d);
+    }
+    if (f == 1u32) {
+        return mul(v_params.degree*real_pow(z, v_params.degree - 1.0f32), d);
+    }
+    if (f == 2u32) {
+        var s: vec2<f32> = sign(z);
+        return mul(2.0f32*s*z, s*d);
+    }
+    var w: vec2<f32> = mul(2.0f32*z, d);
+    if (f == 3u32) {
+        return vec2<f32>(w.x, -w.y);
+    }
+    if (f == 4u32) {
+        return vec2<f32>(sign(z.x*z.x - z.y*z.y)*w.x, w.y);
+    }
+    return vec2<f32>(sign(z.x*z.x - z.y*z.y)*w.x, sign(z.x*z.y)*w.y);
+}
+
+fn compute(index: u32) -> Field {
     var x1: u32 = index % v_params.width;
-    var y1: u32 = index / v_params.height;
+    var y1: u32 = index / v_params.width;
+
+    // Half extents of the image, they differ unless it's square
+    var ox: f32 = map(f32(x1), 0.0f32, f32(v_params.width),  -v_params.range_x, v_params.range_x);
+    var oy: f32 = map(f32(y1), 0.0f32, f32(v_params.height), -v_params.range_y, v_params.range_y);
+    var x: f32 = v_params.m00*ox + v_params.m01*oy + v_params.pos_x;
+    var y: f32 = v_params.m10*ox + v_params.m11*oy + v_params.pos_y;
+
+    var escape:  f32 = v_params.bailout*v_params.bailout;
+    var spacing: f32 = 2.0f32*v_params.range_x / f32(v_params.width);
 
-    var x: f32 = map(f32(x1), 0.0f32, f32(v_params.width),  v_params.range_min, v_params.range_max) + v_params.pos_x;
-    var y: f32 = map(f32(y1), 0.0f32, f32(v_params.height), v_params.range_min, v_params.range_max) + v_params.pos_y;
+    var z: vec2<f32> = vec2<f32>(0.0f32, 0.0f32);
+    var c: vec2<f32> = vec2<f32>(x, y);
+    // Derivative per pixel, dz' = f'(z)*dz + spacing
+    var d:  vec2<f32> = vec2<f32>(0.0f32, 0.0f32);
+    var dc: f32 = spacing;
+
+    if (v_params.julia == 1u32) {
+        // Starts at the pixel with c fixed, the derivative doesn't depend on c
+        z  = c;
+        c  = vec2<f32>(v_params.julia_x, v_params.julia_y);
+        d  = vec2<f32>(spacing, 0.0f32);
+        dc = 0.0f32;
+    } elseif (v_params.formula == 0u32 && v_params.degree == 2.0f32) {
+        // Main cardioid and period-2 bulb, their period is stored instead of |z|^2
+        var xq: f32 = x - 0.25f32;
+        var q:  f32 = xq*xq + y*y;
+        if (q*(q + xq) <= 0.25f32*y*y) {
+            return Field(v_params.max_iter, 1.0f32, 0.0f32);
+        }
+        if ((x+1.0f32)*(x+1.0f32) + y*y <= 0.0625f32) {
+            return Field(v_params.max_iter, 2.0f32, 0.0f32);
+        }
+    }
 
-    var x2: f32 = 0.0f32;
-    var y2: f32 = 0.0f32;
+    // Brent's cycle detection, z is compared to a point saved every power of two iterations
+    var saved:     vec2<f32> = vec2<f32>(0.0f32, 0.0f32);
+    var steps:     u32 = 0u32;
+    var limit:     u32 = 1u32;
+    var period:    u32 = 0u32;
+    var tolerance: f32 = spacing*0.001f32;
+    tolerance = tolerance*tolerance;
 
     var iter: u32 = 0u32;
     loop {
-        if (x2*x2+y2*y2 <= 4.0f32 && iter < v_params.max_iter) {
-            var x_new = x2*x2-y2*y2 + x;
-            y2 = 2.0f32*x2*y2 +y;
-            x2 = x_new;
+        if (dot(z, z) <= escape && iter < v_params.max_iter) {
+            if (v_params.formula == 6u32) {
+                var next: Dual = custom_formula(Dual(z, d), Dual(c, vec2<f32>(dc, 0.0f32)));
+                z = next.v;
+                d = next.d;
+            } else {
+                d = formula_derivative(z, d) + vec2<f32>(dc, 0.0f32);
+                z = formula_step(z) + c;
+            }
             iter = iter + 1u32;
+
+            steps = steps + 1u32;
+            if (dot(z - saved, z - saved) < tolerance) {
+                period = steps;
+                iter = v_params.max_iter;
+                break;
+            }
+            if (steps == limit) {
+                saved = z;
+                steps = 0u32;
+                limit = limit*2u32;
+            }
         } else {
             break;
         }
     }
 
-    //return iter;
-
-    var normalized: f32 = map(f32(iter), 0.0f32, f32(v_params.max_iter), 0.0f32, 1.0f32);
-    return array<u32,3>(
-        u32((9.0f32*(1.0f32-normalized)*normalized*normalized*normalized*255.0f32)),
-        u32((15.0f32*(1.0f32-normalized)*(1.0f32-normalized)*normalized*normalized*255.0f32)),
-        u32((8.5f32*(1.0f32-normalized)*(1.0f32-normalized)*(1.0f32-normalized) * normalized*255.0f32))
-    );
+    if (iter >= v_params.max_iter) {
+        return Field(iter, f32(period), 0.0f32);
+    }
+    // |z|ln|z| / |dz/dc|
+    var norm: f32 = dot(z, z);
+    return Field(iter, norm, sqrt(norm)*log(norm) / 2.0f32 / length(d));
 }
 
 [[stage(compute), workgroup_size(1)]]
 fn main([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
-    v_pixels.data[global_id.x] = compute(global_id.x);
+    v_fields.data[global_id.x] = compute(global_id.x);
     //v_indices.data[123] = compute(global_id.x);
 }
@@ -0,0 +1,211 @@
+use mandelbrot_set::mandelbrot::{
+    MandelbrotParameters, Coloring, Interior,
+    view::{View, Transform},
+    palette::Palette,
+    formula::Formula,
+    newton::Polynomial,
+    tiled::TiledRender,
+    location::Location,
+};
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+const PALETTES_PATH:  &str = "./palettes";
+const OUTPUT_PATH:    &str = "./render.png";
+// Radius of the explorer's starting view, zoom 1 shows the same
+const DEFAULT_RADIUS: f64  = 2.5;
+
+const USAGE: &str = "\
+Renders the Mandelbrot set to an image file without opening a window
+
+Usage: render [options]
+
+Options:
+    --location <path>      Starts from a location saved by the explorer, later options override it
+    --center <re>,<im>     View center, as many digits as needed  [0,0]
+    --zoom <factor>        Magnification of the starting view     [1]
+    --max-iter <n>         Iteration limit                        [100]
+    --size <w>x<h>         Image size in pixels                   [1000x1000]
+    --formula <formula>    mandelbrot, power <n>, burning ship, tricorn, celtic, buffalo
+                           or an expression like 'z^3 + c'        [mandelbrot]
+    --newton <polynomial>  Newton fractal of 'roots ...' or 'coefficients ...'
+    --julia <re>,<im>      Julia set for this c
+    --palette <palette>    Name in ./palettes or a path to a .palette file  [classic]
+    --coloring <coloring>  iteration, smooth, histogram or distance         [smooth]
+    --supersample <n>      Renders n*n samples for every pixel    [1]
+    --cpu                  Never use the GPU
+    --output <path>        Image format follows the extension, PNG and TIFF
+                           are written as they render so any size fits in memory  [./render.png]
+    --help                 Prints this";
+
+struct Options {
+    view:        View,
+    transform:   Transform,
+    max_iter:    u32,
+    width:       u32,
+    height:      u32,
+    formula:     Formula,
+    newton:      Option<Arc<Polynomial>>,
+    julia_c:     Option<(f64,f64)>,
+    palette:     Palette,
+    coloring:    Coloring,
+    interior:    Interior,
+    distance_thickness: Option<f32>,
+    supersample: u32,
+    cpu:         bool,
+    output:      PathBuf,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            view:        View::new((0., 0.), DEFAULT_RADIUS),
+            transform:   Transform::default(),
+            max_iter:    100,
+            width:       1000,
+            height:      1000,
+            formula:     Formula::default(),
+            newton:      None,
+            julia_c:     None,
+            palette:     Palette::classic(),
+            coloring:    Coloring::Smooth,
+            interior:    Interior::Flat,
+            distance_thickness: None,
+            supersample: 1,
+            cpu:         false,
+            output:      PathBuf::from(OUTPUT_PATH),
+        }
+    }
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut options = Self::default();
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                return Ok(None);
+            }
+            if arg == "--cpu" {
+                options.cpu = true;
+                continue;
+            }
+
+            let value = args.next().ok_or_else(|| format!("Missing value for '{}'", arg))?;
+            let err   = |e: String| format!("{}: {}", arg, e);
+            match arg.as_str() {
+                "--location" => {
+                    let location = Location::load(Path::new(&value))?;
+                    options.view      = location.view;
+                    options.transform = location.transform;
+                    options.max_iter  = location.max_iter;
+                    options.formula   = location.formula;
+                    options.newton    = location.newton;
+                    options.julia_c   = location.julia_c;
+                    options.palette   = load_palette(&location.palette).map_err(err)?;
+                    options.coloring  = location.coloring;
+                    options.interior  = location.interior;
+                    options.distance_thickness = Some(location.distance_thickness);
+                },
+                "--center"   => {
+                    let (re, im) = split_pair(&value).map_err(err)?;
+                    // Through the text form so the center keeps every digit it was given
+                    options.view = format!("{} {} {:e}", re, im, options.view.radius()).parse().map_err(err)?;
+                },
+                "--zoom"     => {
+                    let zoom: f64 = parse_number(&value).map_err(err)?;
+                    if !(zoom.is_finite() && zoom > 0.) {
+                        return Err(err(format!("Invalid zoom '{}'", value)));
+                    }
+                    options.view.set_radius(DEFAULT_RADIUS / zoom);
+                },
+                "--max-iter" => {
+                    options.max_iter = parse_number(&value).map_err(err)?;
+                    if options.max_iter == 0 {
+                        return Err(err(format!("invalid max_iter '{}', it has to be at least 1", value)));
+                    }
+                },
+                "--size"     => {
+                    let (width, height) = value.split_once('x')
+                        .ok_or_else(|| err(format!("Expected '<w>x<h>', got '{}'", value)))?;
+                    options.width  = parse_number(width).map_err(err)?;
+                    options.height = parse_number(height).map_err(err)?;
+                },
+                "--formula"  => options.formula  = value.parse().map_err(err)?,
+                "--newton"   => options.newton   = Some(Arc::new(value.parse().map_err(err)?)),
+                "--julia"    => {
+                    let (re, im) = split_pair(&value).map_err(err)?;
+                    options.julia_c = Some((parse_number(re).map_err(err)?, parse_number(im).map_err(err)?));
+                },
+                "--palette"  => options.palette  = load_palette(&value).map_err(err)?,
+                "--coloring" => options.coloring = value.parse().map_err(err)?,
+                "--supersample" => options.supersample = parse_number(&value).map_err(err)?,
+                "--output"   => options.output   = PathBuf::from(value),
+                _ => return Err(format!("Unknown option '{}', see --help", arg)),
+            }
+        }
+
+        if options.width == 0 || options.height == 0 || options.supersample == 0 {
+            return Err("Size and supersampling have to be at least 1".to_string());
+        }
+        Ok(Some(options))
+    }
+}
+
+fn main() -> Result<(), String> {
+    let options = match Options::parse(std::env::args().skip(1))? {
+        Some(options) => options,
+        None => {
+            println!("{}", USAGE);
+            return Ok(());
+        },
+    };
+
+    let mut params = MandelbrotParameters::new(options.width, options.height);
+    params.view      = options.view;
+    params.transform = options.transform;
+    params.max_iter  = options.max_iter;
+    params.palette   = options.palette;
+    params.formula   = options.formula;
+    params.newton    = options.newton;
+    params.julia_c   = options.julia_c;
+    params.coloring  = options.coloring;
+    params.interior  = options.interior;
+    if let Some(thickness) = options.distance_thickness {
+        params.distance_thickness = thickness;
+    }
+
+    let render = TiledRender::new(params, options.supersample, !options.cpu);
+    println!("Rendering {}x{} in bands of {} rows", render.width(), render.height(), render.rows_per_tile());
+    let instant = Instant::now();
+    render.save(&options.output, |done, steps| println!("Tile {}/{}", done, steps))?;
+    println!("Elapsed: {:?}", instant.elapsed());
+    println!("Saved {}", options.output.display());
+    Ok(())
+}
+
+/// Splits `a,b`
+fn split_pair(s: &str) -> Result<(&str, &str), String> {
+    s.split_once(',')
+        .map(|(a, b)| (a.trim(), b.trim()))
+        .ok_or_else(|| format!("Expected '<re>,<im>', got '{}'", s))
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.trim().parse().map_err(|_| format!("Invalid number '{}'", s))
+}
+
+/// `classic`, a palette in ./palettes by name or a path to a palette file
+fn load_palette(name: &str) -> Result<Palette, String> {
+    if name == "classic" {
+        return Ok(Palette::classic());
+    }
+    let path = Path::new(name);
+    if path.is_file() {
+        return Palette::load(path);
+    }
+    Palette::load(&Path::new(PALETTES_PATH).join(format!("{}.palette", name)))
+}
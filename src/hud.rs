@@ -0,0 +1,86 @@
+use sdl2::{
+    pixels::Color,
+    rect::Rect,
+    render::{BlendMode, Canvas, RenderTarget},
+};
+
+// Every font pixel is drawn as a square of this many screen pixels
+const SCALE:   i32 = 2;
+const ADVANCE: i32 = 6; // Glyph width and the gap to the next one
+const LINE:    i32 = 9; // Glyph height and the gap to the next line
+const PADDING: i32 = 6;
+
+/// 5x7 glyphs for ASCII 32 to 126, a byte for every column with the top row in the lowest bit
+const FONT: [[u8;5]; 95] = [
+    [0x00,0x00,0x00,0x00,0x00], [0x00,0x00,0x5F,0x00,0x00], [0x00,0x07,0x00,0x07,0x00], [0x14,0x7F,0x14,0x7F,0x14], // space ! " #
+    [0x24,0x2A,0x7F,0x2A,0x12], [0x23,0x13,0x08,0x64,0x62], [0x36,0x49,0x55,0x22,0x50], [0x00,0x05,0x03,0x00,0x00], // $ % & '
+    [0x00,0x1C,0x22,0x41,0x00], [0x00,0x41,0x22,0x1C,0x00], [0x08,0x2A,0x1C,0x2A,0x08], [0x08,0x08,0x3E,0x08,0x08], // ( ) * +
+    [0x00,0x50,0x30,0x00,0x00], [0x08,0x08,0x08,0x08,0x08], [0x00,0x60,0x60,0x00,0x00], [0x20,0x10,0x08,0x04,0x02], // , - . /
+    [0x3E,0x51,0x49,0x45,0x3E], [0x00,0x42,0x7F,0x40,0x00], [0x42,0x61,0x51,0x49,0x46], [0x21,0x41,0x45,0x4B,0x31], // 0 1 2 3
+    [0x18,0x14,0x12,0x7F,0x10], [0x27,0x45,0x45,0x45,0x39], [0x3C,0x4A,0x49,0x49,0x30], [0x01,0x71,0x09,0x05,0x03], // 4 5 6 7
+    [0x36,0x49,0x49,0x49,0x36], [0x06,0x49,0x49,0x29,0x1E], [0x00,0x36,0x36,0x00,0x00], [0x00,0x56,0x36,0x00,0x00], // 8 9 : ;
+    [0x08,0x14,0x22,0x41,0x00], [0x14,0x14,0x14,0x14,0x14], [0x00,0x41,0x22,0x14,0x08], [0x02,0x01,0x51,0x09,0x06], // < = > ?
+    [0x32,0x49,0x79,0x41,0x3E], [0x7E,0x11,0x11,0x11,0x7E], [0x7F,0x49,0x49,0x49,0x36], [0x3E,0x41,0x41,0x41,0x22], // @ A B C
+    [0x7F,0x41,0x41,0x22,0x1C], [0x7F,0x49,0x49,0x49,0x41], [0x7F,0x09,0x09,0x09,0x01], [0x3E,0x41,0x49,0x49,0x7A], // D E F G
+    [0x7F,0x08,0x08,0x08,0x7F], [0x00,0x41,0x7F,0x41,0x00], [0x20,0x40,0x41,0x3F,0x01], [0x7F,0x08,0x14,0x22,0x41], // H I J K
+    [0x7F,0x40,0x40,0x40,0x40], [0x7F,0x02,0x0C,0x02,0x7F], [0x7F,0x04,0x08,0x10,0x7F], [0x3E,0x41,0x41,0x41,0x3E], // L M N O
+    [0x7F,0x09,0x09,0x09,0x06], [0x3E,0x41,0x51,0x21,0x5E], [0x7F,0x09,0x19,0x29,0x46], [0x46,0x49,0x49,0x49,0x31], // P Q R S
+    [0x01,0x01,0x7F,0x01,0x01], [0x3F,0x40,0x40,0x40,0x3F], [0x1F,0x20,0x40,0x20,0x1F], [0x3F,0x40,0x38,0x40,0x3F], // T U V W
+    [0x63,0x14,0x08,0x14,0x63], [0x07,0x08,0x70,0x08,0x07], [0x61,0x51,0x49,0x45,0x43], [0x00,0x7F,0x41,0x41,0x00], // X Y Z [
+    [0x02,0x04,0x08,0x10,0x20], [0x00,0x41,0x41,0x7F,0x00], [0x04,0x02,0x01,0x02,0x04], [0x40,0x40,0x40,0x40,0x40], // \ ] ^ _
+    [0x00,0x01,0x02,0x04,0x00], [0x20,0x54,0x54,0x54,0x78], [0x7F,0x48,0x44,0x44,0x38], [0x38,0x44,0x44,0x44,0x20], // ` a b c
+    [0x38,0x44,0x44,0x48,0x7F], [0x38,0x54,0x54,0x54,0x18], [0x08,0x7E,0x09,0x01,0x02], [0x0C,0x52,0x52,0x52,0x3E], // d e f g
+    [0x7F,0x08,0x04,0x04,0x78], [0x00,0x44,0x7D,0x40,0x00], [0x20,0x40,0x44,0x3D,0x00], [0x7F,0x10,0x28,0x44,0x00], // h i j k
+    [0x00,0x41,0x7F,0x40,0x00], [0x7C,0x04,0x18,0x04,0x78], [0x7C,0x08,0x04,0x04,0x78], [0x38,0x44,0x44,0x44,0x38], // l m n o
+    [0x7C,0x14,0x14,0x14,0x08], [0x08,0x14,0x14,0x18,0x7C], [0x7C,0x08,0x04,0x04,0x08], [0x48,0x54,0x54,0x54,0x20], // p q r s
+    [0x04,0x3F,0x44,0x40,0x20], [0x3C,0x40,0x40,0x20,0x7C], [0x1C,0x20,0x40,0x20,0x1C], [0x3C,0x40,0x30,0x40,0x3C], // t u v w
+    [0x44,0x28,0x10,0x28,0x44], [0x0C,0x50,0x50,0x50,0x3C], [0x44,0x64,0x54,0x4C,0x44], [0x00,0x08,0x36,0x41,0x00], // x y z {
+    [0x00,0x00,0x7F,0x00,0x00], [0x00,0x41,0x36,0x08,0x00], [0x08,0x04,0x04,0x08,0x04],                              // | } ~
+];
+
+/// Number of lines fitting on a canvas `height` pixels high
+pub fn rows(height: u32) -> usize {
+    ((height as i32 - 2*PADDING) / (LINE*SCALE)).max(1) as usize
+}
+
+/// Draws the lines in the top left corner on a dark box, lines too long for the canvas are wrapped
+pub fn draw<T: RenderTarget>(canvas: &mut Canvas<T>, lines: &[String]) -> Result<(), String> {
+    let (width, _) = canvas.output_size()?;
+    let columns = ((width as i32 - 2*PADDING) / (ADVANCE*SCALE)).max(1) as usize;
+    let rows = lines.iter()
+        .flat_map(|line| {
+            let chars = line.chars().collect::<Vec<char>>();
+            chars.chunks(columns).map(|chunk| chunk.to_vec()).collect::<Vec<Vec<char>>>()
+        })
+        .collect::<Vec<Vec<char>>>();
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let longest = rows.iter().map(|row| row.len()).max().unwrap_or(0) as i32;
+    let background = Rect::new(
+        0, 0,
+        (longest*ADVANCE*SCALE + 2*PADDING) as u32,
+        (rows.len() as i32*LINE*SCALE + 2*PADDING) as u32,
+    );
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+    canvas.fill_rect(background)?;
+    canvas.set_blend_mode(BlendMode::None);
+
+    let mut pixels = Vec::new();
+    for (row, chars) in rows.iter().enumerate() {
+        for (column, c) in chars.iter().enumerate() {
+            let glyph = FONT.get((*c as usize).wrapping_sub(32)).unwrap_or(&FONT['?' as usize - 32]);
+            let x = PADDING + column as i32*ADVANCE*SCALE;
+            let y = PADDING + row    as i32*LINE*SCALE;
+            for (dx, bits) in glyph.iter().enumerate() {
+                for dy in (0..7).filter(|dy| bits >> dy & 1 == 1) {
+                    pixels.push(Rect::new(x + dx as i32*SCALE, y + dy*SCALE, SCALE as u32, SCALE as u32));
+                }
+            }
+        }
+    }
+    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    canvas.fill_rects(&pixels)
+}
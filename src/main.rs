@@ -1,44 +1,63 @@
 extern crate sdl2;
 extern crate image;
 
-mod mandelbrot;
-use mandelbrot::Mandelbrot;
+mod hud;
+
+use mandelbrot_set::mandelbrot;
+use mandelbrot::{Mandelbrot, MandelbrotParameters, Field, buddhabrot::Buddhabrot, location::Location, history::{History, Bookmarks}, view::View, palette::Palette, formula::Formula, expression::Expression, newton::Polynomial};
 
 #[cfg(feature = "gpu")]
 use mandelbrot::gpu::GpuCompute;
 
-use mandelbrot::compute::*; // Compute, ComputeCPU, ComputeGPU
+use mandelbrot::compute::*; // Compute, ComputeFields, ComputeCPU, ComputeDoubleDouble, ComputeGPU
 
 use sdl2::{
     pixels::{
         Color,
         PixelFormatEnum,
     },
-    event::Event,
-    keyboard::Keycode,
+    event::{Event, WindowEvent},
+    keyboard::{Keycode, Mod},
+    video::FullscreenType,
+    mouse::MouseButton,
+    rect::Rect,
 };
 
 use std::{
+    io::Write,
     path::Path,
     time::{Instant,Duration},
     sync::mpsc,
     collections::HashMap,
 };
 
+use std::sync::Arc;
 #[cfg(feature = "gpu")]
-use std::sync::{Arc,Mutex};
+use std::sync::Mutex;
 
+// Size the window opens with, it can be resized afterwards
 const WIDTH:  u32 = 1000;
-const HEIGHT: u32 = WIDTH; // Must be the same as WIDTH
-// Because wgsl dosen't support u8 we need to use u32 thus allocating 4 times more memory
+const HEIGHT: u32 = 1000;
+// The gpu writes a 12 byte field for every pixel
 // Since the buffer size is limited
-// The mandelbrot's size is limited to 3344 by 3344
-const ALIA: u32 = 2344; // WIDTH+ALIA cannot be greater than 3344
+// The antialiased frame is limited to 3344*3344 pixels
+const ALIA: f64 = 3.344; // Scale of both sides, smaller for windows too large for it
+const MAX_ALIA_PIXELS: f64 = 3344.*3344.;
 const ZOOM_FACTOR:      f64 = 0.95;
-const MOV_SPEED_FACTOR: f64 = 0.95;
-const MOVEMENT_SPEED_DEFAULT: f64 = 0.5;
+const THICKNESS_FACTOR: f32 = 1.25;
+const START_RADIUS: f64 = 2.5;
+const WHEEL_ZOOM_STEPS: i32 = 4; // Zoom steps of ZOOM_FACTOR per notch of the mouse wheel
+const CLICK_DISTANCE:   i32 = 2; // Pixels the mouse may move between press and release of a click
+// Key movement is measured in time, so it feels the same however long a frame takes
+const PAN_SPEED:      f64 = 1.;   // Views per second, a view being the shorter side of the window
+const ZOOM_SPEED:     f64 = 2.;   // Natural log of the magnification per second, about 7x
+const ROTATION_SPEED: f64 = 1.;   // Radians per second
+const EASE_TIME:      f64 = 0.15; // Seconds to get most of the way to full speed, and back to rest
+const MAX_STEP:       f64 = 0.25; // Longest step in seconds, so a stalled frame doesn't make the view jump
 
 const SCREENSHOT_PATH: &str = "./screenshot.png";
+const LOCATION_PATH:   &str = "./location.toml";
+const PALETTES_PATH:   &str = "./palettes";
 
 fn main() -> Result<(), String> {
     println!("Mandelbrot!");
@@ -49,26 +68,62 @@ fn main() -> Result<(), String> {
     let window = vid_subsys
         .window("Mandelbrot Explorer", WIDTH, HEIGHT)
         .position_centered()
+        .resizable()
         .opengl()
         .build()
         .map_err(|e| e.to_string())?;
 
     let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
     let mut event_pump = sdl_context.event_pump()?;
-    let mut draw = true;
+    let mut draw    = true;
+    let mut recolor = false;
+
+    let mut palettes = vec![Palette::classic()];
+    palettes.extend(Palette::load_dir(Path::new(PALETTES_PATH)));
+    let mut palette_index = 0;
 
     let mut mandelbrot = Mandelbrot::builder(WIDTH, HEIGHT)
         .max_iter(100)
-        .range((-2.5,2.5))
+        .view(View::new((0.,0.), START_RADIUS))
+        .palette(palettes[palette_index].clone())
         .build();
 
-    let mut zoom:      f64 = 1.;
-    let mut mov_speed: f64 = MOVEMENT_SPEED_DEFAULT;
+    let mut alia_enabled = true;
+
+    // A location file given on launch, as saved with L
+    if let Some(path) = std::env::args().nth(1) {
+        let location = Location::load(Path::new(&path))?;
+        location.apply(mandelbrot.params_mut());
+        palette_index = find_palette(&palettes, &location.palette);
+        mandelbrot.params_mut().palette = palettes[palette_index].clone();
+        alia_enabled = location.antialiasing;
+    }
+
+    // Where the view came to rest, recorded once it stays still for a moment
+    let mut history = History::default();
+    let mut history_pending = true;
+    let mut bookmarks = match Bookmarks::default_path().map(|path| Bookmarks::load(&path)) {
+        Some(Ok(bookmarks)) => Some(bookmarks),
+        Some(Err(e)) => {
+            println!("!----- Loading bookmarks failed: {} -----!", e);
+            None
+        },
+        None => {
+            println!("!----- No config directory for bookmarks -----!");
+            None
+        },
+    };
+
+    // Current size of the window, the mandelbrot and the texture follow it
+    let (mut width, mut height) = (WIDTH, HEIGHT);
+    let mut resized: Option<(u32,u32)> = None;
 
     let texture_creator = canvas.texture_creator();
-    let mut texture = texture_creator.create_texture_static(PixelFormatEnum::RGB24, WIDTH, HEIGHT).unwrap();
+    let mut texture = texture_creator.create_texture_static(PixelFormatEnum::RGB24, width, height).unwrap();
 
-    let mut alia_rx: Option<mpsc::Receiver<Vec<u8>>> = None;
+    let mut alia_rx: Option<mpsc::Receiver<AliaFrame>> = None;
+    // Fields of the last antialiased frame, a recolor colors them again instead of iterating
+    let mut alia_fields: Option<Arc<Vec<Field>>> = None;
 
     let alia_pool = rayon::ThreadPoolBuilder::new()
         .num_threads(2)
@@ -76,26 +131,178 @@ fn main() -> Result<(), String> {
         .unwrap();
 
     let mut keys_pressed = HashMap::new();
+    // Velocities of the view, they ease towards what the keys held down ask for
+    let mut motion    = Motion::default();
+    let mut last_step = Instant::now();
+
+    // Mouse position over the window
+    let mut mouse_pos: Option<(i32,i32)> = None;
+    // Left button held down, where it was pressed and whether it selects a box to zoom into
+    let mut drag: Option<((i32,i32), bool)> = None;
+    // Mandelbrot view to go back to while a Julia set is shown
+    let mut mandelbrot_view: Option<View> = None;
+    // Text being typed in, keys go to it instead of the controls until Return or Escape
+    let mut prompt: Option<(Prompt, String)> = None;
+    // Bookmark list in the overlay and the entry selected in it, keys go to it until Return or Escape
+    let mut bookmark_list: Option<usize> = None;
+    let text_input = vid_subsys.text_input();
+    text_input.stop();
+
+    // Redraws the window without computing anything, for the overlays
+    let mut refresh    = false;
+    let mut show_hud   = true;
+    let mut frame_time = Duration::ZERO;
 
     let mut is_alia      = false;
-    let mut alia_enabled = true;
     let mut should_alia  = true;
     let mut alia_timer   = Instant::now();
 
     #[cfg(feature = "gpu")]
-    let alia_gpu_compute: Option<Arc<Mutex<GpuCompute>>> =
-        match GpuCompute::new(((WIDTH+ALIA)*(HEIGHT+ALIA)*3) as usize) {
+    let alia_gpu_compute: Option<Arc<Mutex<GpuCompute>>> = {
+        // Sized for the first antialiased frame, the buffer follows the window from there
+        let (alia_width, alia_height) = alia_size(width, height);
+        match GpuCompute::new((alia_width*alia_height) as usize) {
             Some(gpu_compute) => Some(Arc::new(Mutex::new(gpu_compute))),
             None => {
                 println!("!----- GPU computing is not supported -----!");
                 None
             },
-        };
+        }
+    };
 
     'main: loop {
+        // Location picked from the history or the bookmarks
+        let mut jump: Option<Location> = None;
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } => break 'main,
+                Event::MouseMotion { x, y, .. } => {
+                    match (drag, mouse_pos) {
+                        // The point grabbed stays under the cursor
+                        (Some((_, false)), Some(last)) => {
+                            let from = mandelbrot.params().offset_at(last.0 as f64, last.1 as f64);
+                            let to   = mandelbrot.params().offset_at(x as f64, y as f64);
+                            mandelbrot.params_mut().view.translate(from.0 - to.0, from.1 - to.1);
+                            draw = true;
+                        },
+                        (Some((_, true)), _) => { refresh = true; },
+                        _ => {}
+                    }
+                    mouse_pos = Some((x, y));
+                    // The point under the cursor changed
+                    refresh |= show_hud;
+                },
+                Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } => {
+                    let shift = sdl_context.keyboard().mod_state().intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    drag = Some(((x, y), shift));
+                },
+                Event::MouseButtonUp { mouse_btn: MouseButton::Left, x, y, .. } => {
+                    if let Some((start, is_box)) = drag.take() {
+                        let (box_width, box_height) = ((x-start.0).abs(), (y-start.1).abs());
+                        if box_width.max(box_height) <= CLICK_DISTANCE {
+                            // A click centers the view on the point
+                            let offset = mandelbrot.params().offset_at(x as f64, y as f64);
+                            mandelbrot.params_mut().view.translate(offset.0, offset.1);
+                        } else if is_box {
+                            // The whole box fits on screen afterwards
+                            let offset = mandelbrot.params().offset_at((start.0+x) as f64 / 2., (start.1+y) as f64 / 2.);
+                            let factor = (box_width as f64 / width as f64).max(box_height as f64 / height as f64);
+                            mandelbrot.params_mut().view.translate(offset.0, offset.1);
+                            mandelbrot.params_mut().view.zoom(factor);
+                        }
+                        draw = true;
+                    }
+                },
+                Event::MouseWheel { y: notches, .. } => {
+                    // The point under the cursor stays where it is
+                    let factor = ZOOM_FACTOR.powi(notches*WHEEL_ZOOM_STEPS);
+                    let offset = match mouse_pos {
+                        Some((x, y)) => mandelbrot.params().offset_at(x as f64, y as f64),
+                        None => (0., 0.),
+                    };
+                    mandelbrot.params_mut().view.translate(offset.0*(1.-factor), offset.1*(1.-factor));
+                    mandelbrot.params_mut().view.zoom(factor);
+                    draw = true;
+                },
+                // Only the last size of the frame is applied
+                Event::Window { win_event: WindowEvent::SizeChanged(w, h), .. } if w > 0 && h > 0 => {
+                    resized = Some((w as u32, h as u32));
+                },
+                Event::Window { win_event: WindowEvent::Leave, .. } => {
+                    mouse_pos = None;
+                    refresh   = true;
+                },
+                Event::TextInput { text, .. } => {
+                    if let Some((kind, input)) = &mut prompt {
+                        input.push_str(&text);
+                        print!("\r{}{} ", kind.label(), input);
+                        std::io::stdout().flush().unwrap_or(());
+                    }
+                },
+                Event::KeyDown { keycode: key, .. } if prompt.is_some() => {
+                    match key {
+                        Some(Keycode::Return) => {
+                            text_input.stop();
+                            println!();
+                            let (kind, input) = prompt.take().unwrap();
+                            if kind == Prompt::Bookmark {
+                                let location = Location::new(mandelbrot.params(), alia_enabled);
+                                match bookmarks.as_mut().map(|bookmarks| bookmarks.set(input.trim(), location)) {
+                                    Some(Ok(())) => println!("!----- Bookmarked '{}' -----!", input.trim()),
+                                    Some(Err(e)) => println!("!----- Saving the bookmark failed: {} -----!", e),
+                                    None => {},
+                                }
+                            // Polynomials for the Newton fractal start with how they are given
+                            } else if input.starts_with("roots") || input.starts_with("coefficients") {
+                                match input.parse::<Polynomial>() {
+                                    Ok(polynomial) => {
+                                        println!("Newton fractal: {}", polynomial);
+                                        mandelbrot.params_mut().newton = Some(Arc::new(polynomial));
+                                        draw = true;
+                                    },
+                                    Err(e) => println!("!----- {} -----!", e),
+                                }
+                            } else {
+                                match input.parse::<Expression>() {
+                                    Ok(expression) => {
+                                        mandelbrot.params_mut().formula = Formula::Custom(Arc::new(expression));
+                                        mandelbrot.params_mut().newton  = None;
+                                        println!("Formula: {}", mandelbrot.params().formula);
+                                        draw = true;
+                                    },
+                                    Err(e) => println!("!----- {} -----!", e),
+                                }
+                            }
+                        },
+                        Some(Keycode::Escape) => {
+                            text_input.stop();
+                            prompt = None;
+                            println!();
+                        },
+                        Some(Keycode::Backspace) => {
+                            let (kind, input) = prompt.as_mut().unwrap();
+                            input.pop();
+                            print!("\r{}{} ", kind.label(), input);
+                            std::io::stdout().flush().unwrap_or(());
+                        },
+                        _ => {}
+                    }
+                },
+                Event::KeyDown { keycode: key, .. } if bookmark_list.is_some() => {
+                    let entries  = bookmarks.as_ref().map_or(&[][..], |bookmarks| bookmarks.entries());
+                    let selected = bookmark_list.as_mut().unwrap();
+                    match key {
+                        Some(Keycode::Up)   => *selected = selected.saturating_sub(1),
+                        Some(Keycode::Down) => *selected = (*selected+1).min(entries.len().saturating_sub(1)),
+                        Some(Keycode::Return) => {
+                            jump = entries.get(*selected).map(|(_, location)| location.clone());
+                            bookmark_list = None;
+                        },
+                        Some(Keycode::Escape) | Some(Keycode::H) => bookmark_list = None,
+                        _ => {}
+                    }
+                    refresh = true;
+                },
                 Event::KeyUp { keycode: key, .. } => {
                     match key {
                         Some(Keycode::E) => { keys_pressed.insert(Keycode::E, false); },
@@ -106,6 +313,8 @@ fn main() -> Result<(), String> {
                         Some(Keycode::D) => { keys_pressed.insert(Keycode::D, false); },
                         Some(Keycode::K) => { keys_pressed.insert(Keycode::K, false); },
                         Some(Keycode::J) => { keys_pressed.insert(Keycode::J, false); },
+                        Some(Keycode::Z) => { keys_pressed.insert(Keycode::Z, false); },
+                        Some(Keycode::X) => { keys_pressed.insert(Keycode::X, false); },
                         _ => {}
                     }
                 },
@@ -119,16 +328,149 @@ fn main() -> Result<(), String> {
                         Some(Keycode::D) => { keys_pressed.insert(Keycode::D, true); },
                         Some(Keycode::K) => { keys_pressed.insert(Keycode::K, true); },
                         Some(Keycode::J) => { keys_pressed.insert(Keycode::J, true); },
-                        Some(Keycode::R) => { alia_enabled = !alia_enabled; should_alia = true; },
+                        Some(Keycode::Z) => { keys_pressed.insert(Keycode::Z, true); },
+                        Some(Keycode::X) => { keys_pressed.insert(Keycode::X, true); },
+                        Some(Keycode::V) => {
+                            mandelbrot.params_mut().transform = Default::default();
+                            draw = true;
+                        },
+                        Some(Keycode::R) => { alia_enabled = !alia_enabled; should_alia = true; refresh = true; },
+                        Some(Keycode::O) => { show_hud = !show_hud; refresh = true; },
+                        Some(Keycode::F11) => {
+                            let window = canvas.window_mut();
+                            let fullscreen = match window.fullscreen_state() {
+                                FullscreenType::Off => FullscreenType::Desktop,
+                                _ => FullscreenType::Off,
+                            };
+                            window.set_fullscreen(fullscreen).unwrap_or_else(|e| println!("!----- {} -----!", e));
+                        },
                         Some(Keycode::G) => { mandelbrot.on_gpu = !mandelbrot.on_gpu; },
+                        Some(Keycode::P) => {
+                            palette_index = (palette_index+1) % palettes.len();
+                            println!("Palette: {}", palettes[palette_index].name);
+                            mandelbrot.params_mut().palette = palettes[palette_index].clone();
+                            recolor = true;
+                        },
+                        Some(Keycode::LeftBracket) => {
+                            mandelbrot.params_mut().distance_thickness /= THICKNESS_FACTOR;
+                            recolor = true;
+                        },
+                        Some(Keycode::RightBracket) => {
+                            mandelbrot.params_mut().distance_thickness *= THICKNESS_FACTOR;
+                            recolor = true;
+                        },
+                        Some(Keycode::Tab) => {
+                            match mandelbrot_view.take() {
+                                Some(view) => {
+                                    println!("!----- Mandelbrot set -----!");
+                                    mandelbrot.params_mut().julia_c = None;
+                                    mandelbrot.params_mut().view    = view;
+                                },
+                                None => {
+                                    // The point under the cursor, or the center if the cursor isn't over the window
+                                    let params = mandelbrot.params();
+                                    let center = params.view.center_f64();
+                                    let c = match mouse_pos {
+                                        Some((x, y)) => {
+                                            let x = (x.max(0) as u32).min(width-1);
+                                            let y = (y.max(0) as u32).min(height-1);
+                                            let offset = params.pixel_offset(y*width + x);
+                                            (center.0 + offset.0, center.1 + offset.1)
+                                        },
+                                        None => center,
+                                    };
+                                    println!("!----- Julia set for {} {} -----!", c.0, c.1);
+                                    mandelbrot_view = Some(params.view.clone());
+
+                                    mandelbrot.params_mut().julia_c = Some(c);
+                                    mandelbrot.params_mut().view    = View::new((0., 0.), 2.);
+                                },
+                            }
+                            draw = true;
+                        },
+                        Some(Keycode::F) => {
+                            mandelbrot.params_mut().formula = mandelbrot.params().formula.next();
+                            mandelbrot.params_mut().newton  = None;
+                            println!("Formula: {}", mandelbrot.params().formula);
+                            draw = true;
+                        },
+                        Some(Keycode::Return) => {
+                            println!("!----- Type a formula, Return to render, Escape to cancel -----!");
+                            print!("{}", Prompt::Formula.label());
+                            std::io::stdout().flush().unwrap_or(());
+                            prompt = Some((Prompt::Formula, String::new()));
+                            text_input.start();
+                        },
+                        Some(Keycode::I) => {
+                            mandelbrot.params_mut().interior = mandelbrot.params().interior.next();
+                            recolor = true;
+                        },
+                        Some(Keycode::C) => {
+                            mandelbrot.params_mut().coloring = mandelbrot.params().coloring.next();
+                            recolor = true;
+                        },
+                        Some(Keycode::B) | Some(Keycode::N) => {
+                            println!("!----- Rendering Buddhabrot -----!");
+                            let mut buddhabrot = Buddhabrot::new(mandelbrot.params().clone());
+                            // Nebulabrot, red for the slow orbits and blue for the fast ones
+                            if key == Some(Keycode::N) {
+                                let max_iter = mandelbrot.params().max_iter;
+                                buddhabrot.limits = [max_iter*10, max_iter, (max_iter/10).max(1)];
+                            }
+
+                            // Shows up like an antialiased frame and stays until the view changes
+                            let (tx,rx) = mpsc::channel();
+                            alia_rx = Some(rx);
+                            alia_pool.spawn(move|| {
+                                let now = Instant::now();
+                                tx.send(AliaFrame { pixels: buddhabrot.compute(), fields: None }).unwrap_or(());
+                                println!("Buddhabrot elapsed: {:?}", now.elapsed());
+                            });
+                            is_alia     = false;
+                            should_alia = false;
+                        },
+                        Some(Keycode::L) => {
+                            let location = Location::new(mandelbrot.params(), alia_enabled);
+                            match location.save(Path::new(LOCATION_PATH)) {
+                                Ok(()) => println!("!----- Location saved to {} -----!", LOCATION_PATH),
+                                Err(e) => println!("!----- Saving the location failed: {} -----!", e),
+                            }
+                        },
+                        Some(Keycode::M) if bookmarks.is_some() => {
+                            println!("!----- Type a name for the bookmark, Return to save, Escape to cancel -----!");
+                            print!("{}", Prompt::Bookmark.label());
+                            std::io::stdout().flush().unwrap_or(());
+                            prompt = Some((Prompt::Bookmark, String::new()));
+                            text_input.start();
+                        },
+                        Some(Keycode::H) if bookmarks.is_some() => {
+                            bookmark_list = Some(0);
+                            refresh = true;
+                        },
+                        Some(Keycode::U) | Some(Keycode::Y)
+                        | Some(Keycode::Num1) | Some(Keycode::Num2) | Some(Keycode::Num3)
+                        | Some(Keycode::Num4) | Some(Keycode::Num5) | Some(Keycode::Num6)
+                        | Some(Keycode::Num7) | Some(Keycode::Num8) | Some(Keycode::Num9) => {
+                            jump = match key {
+                                Some(Keycode::U) => history.undo().cloned(),
+                                Some(Keycode::Y) => history.redo().cloned(),
+                                _ => {
+                                    let n = key.unwrap() as i32 - Keycode::Num1 as i32;
+                                    bookmarks.iter()
+                                        .flat_map(|bookmarks| bookmarks.entries())
+                                        .nth(n as usize)
+                                        .map(|(_, location)| location.clone())
+                                },
+                            };
+                        },
                         Some(Keycode::Space) => {
                             println!("!----- Screenshot -----!");
                             let pixels = mandelbrot.pixels();
                             image::save_buffer(
                                 Path::new(SCREENSHOT_PATH),
                                 pixels,
-                                WIDTH,
-                                HEIGHT,
+                                width,
+                                height,
                                 image::ColorType::Rgb8
                             ).unwrap();
                         },
@@ -139,114 +481,336 @@ fn main() -> Result<(), String> {
             }
         }
 
+        if let Some(location) = jump {
+            location.apply(mandelbrot.params_mut());
+            palette_index = find_palette(&palettes, &location.palette);
+            mandelbrot.params_mut().palette = palettes[palette_index].clone();
+            alia_enabled = location.antialiasing;
+            // Tab would go back to a view from before the jump
+            if location.julia_c.is_none() {
+                mandelbrot_view = None;
+            }
+            draw = true;
+        }
+
+        // Time since the last step, however long computing the frame took
+        let step = last_step.elapsed().as_secs_f64().min(MAX_STEP);
+        last_step = Instant::now();
+
+        let mut target = Motion::default();
         for (key, pressed) in keys_pressed.iter() {
             if !pressed {continue;}
             match key {
-                Keycode::E => {
-                    mandelbrot.params_mut().range.0 *= ZOOM_FACTOR;
-                    mandelbrot.params_mut().range.1 *= ZOOM_FACTOR;
-                    zoom += 1.;
-                    mov_speed *= MOV_SPEED_FACTOR;
+                Keycode::E => { target.zoom += ZOOM_SPEED; },
+                Keycode::Q => { target.zoom -= ZOOM_SPEED; },
+                Keycode::W => { target.pan.1 -= PAN_SPEED; },
+                Keycode::S => { target.pan.1 += PAN_SPEED; },
+                Keycode::A => { target.pan.0 -= PAN_SPEED; },
+                Keycode::D => { target.pan.0 += PAN_SPEED; },
+                Keycode::Z => { target.rotation += ROTATION_SPEED; },
+                Keycode::X => { target.rotation -= ROTATION_SPEED; },
+                Keycode::K => { mandelbrot.params_mut().max_iter += 10; draw = true; },
+                Keycode::J if mandelbrot.params().max_iter > 1 => {
+                    // Never below a single iteration, loaded locations can have any count
+                    let max_iter = &mut mandelbrot.params_mut().max_iter;
+                    *max_iter = max_iter.saturating_sub(10).max(1);
+                    draw = true;
                 },
-                Keycode::Q => {
-                    mandelbrot.params_mut().range.0 /= ZOOM_FACTOR;
-                    mandelbrot.params_mut().range.1 /= ZOOM_FACTOR;
-                    zoom -= 1.;
-                    mov_speed /= MOV_SPEED_FACTOR;
-                },
-                Keycode::W => { mandelbrot.params_mut().pos.1 -= mov_speed; },
-                Keycode::S => { mandelbrot.params_mut().pos.1 += mov_speed; },
-                Keycode::A => { mandelbrot.params_mut().pos.0 -= mov_speed; },
-                Keycode::D => { mandelbrot.params_mut().pos.0 += mov_speed; },
-                Keycode::K => { mandelbrot.params_mut().max_iter += 10; },
-                Keycode::J => { if mandelbrot.params().max_iter != 0 { mandelbrot.params_mut().max_iter -= 10; } },
                 _ => {}
             }
+        }
+
+        motion.ease(&target, step);
+        if motion.is_moving() {
+            // Panning is relative to the view's size, so it keeps its pace on screen at any depth
+            let distance = 2.*mandelbrot.params().view.radius()*step;
+            // Movement follows the rotated axes of the screen
+            let (dx, dy) = mandelbrot.params().transform.apply((motion.pan.0*distance, motion.pan.1*distance));
+            mandelbrot.params_mut().view.translate(dx, dy);
+            mandelbrot.params_mut().view.zoom((-motion.zoom*step).exp());
+            mandelbrot.params_mut().transform.rotate(motion.rotation*step);
             draw = true;
         }
 
-        if draw {
-            println!("{:#?}",              mandelbrot.params());
-            println!("Zoom/factor: {} {}", zoom, ZOOM_FACTOR);
-            println!("Mov/factor : {} {}", mov_speed, MOV_SPEED_FACTOR);
-            canvas.set_draw_color(Color::RGB(0, 0, 0));
+        // Everything sized by the window is allocated again, the gpu buffers follow on the next compute
+        if let Some((w, h)) = resized.take() {
+            if (w, h) != (width, height) {
+                (width, height) = (w, h);
+                mandelbrot.set_dimensions(width, height);
+                texture = texture_creator.create_texture_static(PixelFormatEnum::RGB24, width, height).unwrap();
+                draw = true;
+            }
+        }
 
-            println!("drawing");
-            let instant = Instant::now();
-            canvas.clear();
+        if history_pending && !draw && !recolor && alia_timer.elapsed() > Duration::from_millis(500) {
+            history.push(Location::new(mandelbrot.params(), alia_enabled));
+            history_pending = false;
+        }
 
+        if draw {
+            let instant = Instant::now();
             mandelbrot.update();
-            texture.update(None, mandelbrot.pixels(), (WIDTH*3) as usize).unwrap(); // last parm - bytes in a row
+            texture.update(None, mandelbrot.pixels(), (width*3) as usize).unwrap(); // last parm - bytes in a row
+            frame_time = instant.elapsed();
 
-            canvas.copy(&texture, None, None).unwrap();
-
-            canvas.present();
-            let elapsed = instant.elapsed();
-            println!("frame");
-            println!("Elapsed: {:?}", elapsed);
-
-            draw = false;
+            refresh = true;
+            draw    = false;
+            recolor = false;
+            history_pending = true;
+            is_alia     = false;
+            should_alia = true;
+            alia_rx     = None;
+            alia_fields = None;
+            alia_timer  = Instant::now();
+        } else if recolor {
+            // The fields are still valid, only the coloring pass has to run
+            mandelbrot.recolor();
+            texture.update(None, mandelbrot.pixels(), (width*3) as usize).unwrap(); // last parm - bytes in a row
+
+            refresh = true;
+            recolor = false;
+            history_pending = true;
             is_alia     = false;
             should_alia = true;
             alia_rx     = None;
             alia_timer  = Instant::now();
+
+            // So are the antialiased frame's
+            if let (true, Some(fields)) = (alia_enabled, alia_fields.clone()) {
+                let (tx,rx) = mpsc::channel();
+                alia_rx = Some(rx);
+
+                let size   = alia_size(width, height);
+                let params = alia_params(&mandelbrot, size, width);
+                alia_pool.spawn(move|| {
+                    let pixels = alia_pixels(&params, &fields, size, (width, height));
+                    tx.send(AliaFrame { pixels, fields: Some(fields) }).unwrap_or(());
+                });
+                should_alia = false;
+            }
         } else if alia_enabled && !is_alia && should_alia && alia_timer.elapsed() > Duration::from_millis(500) {
             println!("!----- Thread created -----!");
             let (tx,rx) = mpsc::channel();
             alia_rx = Some(rx);
 
-            #[cfg(feature = "gpu")]
-            let mut compute: Box<dyn Compute+Send> =
-                if mandelbrot.on_gpu && alia_gpu_compute.is_some() {
-                    let mut params = *mandelbrot.params();
-                    params.set_dimensions(WIDTH+ALIA, HEIGHT+ALIA);
-                    Box::new(ComputeGPU::new(params, Arc::clone(alia_gpu_compute.as_ref().unwrap())))
-                } else {
-                    let mut mandelbrot_copy = mandelbrot.clone();
-                    mandelbrot_copy.set_dimensions(WIDTH+ALIA, HEIGHT+ALIA);
-                    Box::new(ComputeCPU::new(mandelbrot_copy))
-                };
-            #[cfg(not(feature = "gpu"))]
-            let mut mandelbrot_copy = mandelbrot.clone();
-            #[cfg(not(feature = "gpu"))]
-            mandelbrot_copy.set_dimensions(WIDTH+ALIA, HEIGHT+ALIA);
-            #[cfg(not(feature = "gpu"))]
-            let mut compute = ComputeCPU::new(mandelbrot_copy);
+            let size   = alia_size(width, height);
+            let params = alia_params(&mandelbrot, size, width);
+
+            let mut compute = alia_compute(
+                &mandelbrot,
+                params.clone(),
+                size,
+                #[cfg(feature = "gpu")]
+                alia_gpu_compute.as_ref(),
+            );
 
             alia_pool.spawn(move|| {
                 let now = Instant::now();
-                let mut img = image::DynamicImage::new_rgb8(WIDTH+ALIA,HEIGHT+ALIA);
-
-                let pixels = compute.compute();
-                let mut iter = pixels.iter();
-                for pixel in img.as_mut_rgb8().unwrap().pixels_mut() {
-                    *pixel = image::Rgb([
-                        *iter.next().unwrap(),
-                        *iter.next().unwrap(),
-                        *iter.next().unwrap()
-                    ]);
-                }
-                let new = img.resize(WIDTH,HEIGHT,image::imageops::FilterType::Lanczos3);
-                //let new = new.blur(0.5);
-                tx.send(new.into_bytes()).unwrap_or(());
+                let fields = Arc::new(compute.compute_fields());
+                let pixels = alia_pixels(&params, &fields, size, (width, height));
+                tx.send(AliaFrame { pixels, fields: Some(fields) }).unwrap_or(());
                 println!("Alia elapsed: {:?}", now.elapsed());
             });
             should_alia = false;
         } else if let Some(rx) = &alia_rx {
-            if let Ok(vec) = rx.try_recv() {
+            if let Ok(frame) = rx.try_recv() {
                 println!("!----- Received alia -----!");
-                mandelbrot.set_pixels(vec);
-                texture.update(None, mandelbrot.pixels(), (WIDTH*3) as usize).unwrap(); // last parm - bytes in a row
-                canvas.copy(&texture, None, None).unwrap();
-                canvas.present();
+                mandelbrot.set_pixels(frame.pixels);
+                if frame.fields.is_some() {
+                    alia_fields = frame.fields;
+                }
+                texture.update(None, mandelbrot.pixels(), (width*3) as usize).unwrap(); // last parm - bytes in a row
 
+                refresh = true;
                 is_alia = true;
             }
         }
 
+        if refresh {
+            canvas.set_draw_color(Color::RGB(0, 0, 0));
+            canvas.clear();
+            canvas.copy(&texture, None, None).unwrap();
+            if let (Some((start, true)), Some((x, y))) = (drag, mouse_pos) {
+                canvas.set_draw_color(Color::RGB(255, 255, 255));
+                let selection = Rect::new(start.0.min(x), start.1.min(y), (x-start.0).unsigned_abs(), (y-start.1).unsigned_abs());
+                canvas.draw_rect(selection).unwrap_or(());
+            }
+            if let Some(selected) = bookmark_list {
+                let entries = bookmarks.as_ref().map_or(&[][..], |bookmarks| bookmarks.entries());
+                hud::draw(&mut canvas, &bookmark_lines(entries, selected, hud::rows(height))).unwrap_or(());
+            } else if show_hud {
+                let antialiasing = match (alia_enabled, is_alia) {
+                    (false, _)    => "off",
+                    (true, true)  => "on",
+                    (true, false) => "on, rendering",
+                };
+                hud::draw(&mut canvas, &hud_lines(&mandelbrot, mouse_pos, frame_time, antialiasing)).unwrap_or(());
+            }
+            canvas.present();
+            refresh = false;
+        }
+
         std::thread::sleep(Duration::from_millis(10));
     }
 
 
     Ok(())
 }
+
+/// Size of the antialiased frame, both sides are scaled the same so the aspect ratio is kept
+fn alia_size(width: u32, height: u32) -> (u32, u32) {
+    // Never smaller than the window, frames the gpu can't hold are computed on the cpu
+    let scale = ALIA.min((MAX_ALIA_PIXELS / (width as f64*height as f64)).sqrt()).max(1.);
+    ((width as f64*scale) as u32, (height as f64*scale) as u32)
+}
+
+/// Parameters of the antialiased frame `size` pixels large of a window `width` pixels wide
+fn alia_params(mandelbrot: &Mandelbrot, size: (u32, u32), width: u32) -> MandelbrotParameters {
+    let mut params = mandelbrot.params().clone();
+    params.set_dimensions(size.0, size.1);
+    // Filaments keep their on-screen thickness after downsampling
+    params.distance_thickness *= size.0 as f32 / width as f32;
+    params
+}
+
+/// Colors the fields of an antialiased frame `size` pixels large and downsamples it to the window
+fn alia_pixels(params: &MandelbrotParameters, fields: &[Field], size: (u32, u32), window: (u32, u32)) -> Vec<u8> {
+    let mut pixels = vec![0u8;fields.len()*3];
+    Mandelbrot::color_fields(params, fields, &mut pixels);
+    let img = image::RgbImage::from_raw(size.0, size.1, pixels).unwrap();
+    image::DynamicImage::ImageRgb8(img)
+        .resize_exact(window.0, window.1, image::imageops::FilterType::Lanczos3)
+        .into_bytes()
+}
+
+/// Backend for the antialiased frame of `params`, which are `size` pixels large
+fn alia_compute(
+    mandelbrot: &Mandelbrot,
+    params: MandelbrotParameters,
+    size: (u32, u32),
+    #[cfg(feature = "gpu")]
+    gpu_compute: Option<&Arc<Mutex<GpuCompute>>>,
+) -> Box<dyn ComputeFields+Send> {
+    #[cfg(feature = "gpu")]
+    if let Some(gpu_compute) = gpu_compute {
+        // Deeper views go through a backend with enough precision
+        if mandelbrot.on_gpu && params.supports_gpu() {
+            return Box::new(ComputeGPU::new(params, Arc::clone(gpu_compute)));
+        }
+    }
+    if params.needs_double_double() && !params.needs_perturbation() {
+        return Box::new(ComputeDoubleDouble::new(params));
+    }
+    let mut mandelbrot_copy = mandelbrot.clone();
+    mandelbrot_copy.set_dimensions(size.0, size.1);
+    *mandelbrot_copy.params_mut() = params;
+    Box::new(ComputeCPU::new(mandelbrot_copy))
+}
+
+/// Antialiased frame downsampled to the window,
+/// with the fields it was colored from unless it's a Buddhabrot
+struct AliaFrame {
+    pixels: Vec<u8>,
+    fields: Option<Arc<Vec<Field>>>,
+}
+
+/// What the text typed in is for
+#[derive(Clone, Copy, PartialEq)]
+enum Prompt {
+    Formula,
+    Bookmark,
+}
+
+impl Prompt {
+    fn label(self) -> &'static str {
+        match self {
+            Prompt::Formula  => "z = ",
+            Prompt::Bookmark => "Bookmark name: ",
+        }
+    }
+}
+
+/// Velocities of the view, panning in views per second, zooming and rotating per second
+#[derive(Default)]
+struct Motion {
+    pan:      (f64, f64),
+    zoom:     f64,
+    rotation: f64,
+}
+
+impl Motion {
+    /// Moves the velocities towards the target, the same part of the way in the same time
+    fn ease(&mut self, target: &Motion, step: f64) {
+        let t = 1. - (-step / EASE_TIME).exp();
+        self.pan.0    += (target.pan.0    - self.pan.0)    * t;
+        self.pan.1    += (target.pan.1    - self.pan.1)    * t;
+        self.zoom     += (target.zoom     - self.zoom)     * t;
+        self.rotation += (target.rotation - self.rotation) * t;
+        // Comes to rest instead of creeping forever
+        if !self.is_moving() {
+            *self = Motion::default();
+        }
+    }
+
+    fn is_moving(&self) -> bool {
+        [self.pan.0, self.pan.1, self.zoom, self.rotation].iter().any(|v| v.abs() > 1e-3)
+    }
+}
+
+/// Index of the palette with this name, the first one if there is none
+fn find_palette(palettes: &[Palette], name: &str) -> usize {
+    palettes.iter().position(|palette| palette.name == name).unwrap_or_else(|| {
+        println!("!----- Palette '{}' not found -----!", name);
+        0
+    })
+}
+
+/// Lines of the overlay, the digits shown are enough to tell pixels apart
+fn hud_lines(mandelbrot: &Mandelbrot, mouse_pos: Option<(i32,i32)>, frame_time: Duration, antialiasing: &str) -> Vec<String> {
+    let params = mandelbrot.params();
+    let digits = (-params.pixel_spacing().log10()).ceil().max(0.) as usize + 3;
+    let center = params.view.center_text(digits);
+    let skipped = match mandelbrot.skipped_iterations() {
+        0 => String::new(),
+        skipped => format!(" ({} skipped)", skipped),
+    };
+
+    let mut lines = vec![
+        format!("Re      {}", center.0),
+        format!("Im      {}", center.1),
+        format!("Zoom    10^{:.2}", (START_RADIUS / params.view.radius()).log10()),
+        format!("Iter    {}{}", params.max_iter, skipped),
+        format!("Backend {}", mandelbrot.backend()),
+        format!("Frame   {:.1} ms", frame_time.as_secs_f64()*1000.),
+        format!("AA      {}", antialiasing),
+    ];
+    if let Some((re, im)) = params.julia_c {
+        lines.push(format!("Julia   {} {}", re, im));
+    }
+    if let Some((x, y)) = mouse_pos {
+        // Through the view so the point keeps every digit of the center
+        let offset = params.offset_at(x as f64, y as f64);
+        let mut view = params.view.clone();
+        view.translate(offset.0, offset.1);
+        let point = view.center_text(digits);
+        lines.push(format!("Mouse   {} {}", point.0, point.1));
+    }
+    lines
+}
+
+/// Lines of the bookmark list, scrolled so the selected entry is among the `rows` shown
+fn bookmark_lines(entries: &[(String, Location)], selected: usize, rows: usize) -> Vec<String> {
+    let mut lines = vec!["Bookmarks  Up/Down, Return jumps, Escape closes".to_string()];
+    if entries.is_empty() {
+        lines.push("None yet, M bookmarks the view".to_string());
+        return lines;
+    }
+
+    let rows  = rows.saturating_sub(1).max(1);
+    let first = (selected+1).saturating_sub(rows);
+    for (n, (name, _)) in entries.iter().enumerate().skip(first).take(rows) {
+        let marker = if n == selected { '>' } else { ' ' };
+        lines.push(format!("{} {:>3} {}", marker, n+1, name));
+    }
+    lines
+}
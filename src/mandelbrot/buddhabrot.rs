@@ -0,0 +1,244 @@
+use rayon::prelude::*;
+
+use super::{Mandelbrot, MandelbrotParameters};
+use super::compute::Compute;
+
+// Orbits are started from c in the square of this radius around 0
+const SAMPLE_RADIUS: f64 = 2.;
+const SAMPLES_PER_PIXEL: u64 = 16;
+// Samples handed to a single rayon task
+const CHUNK_SIZE: u64 = 1 << 14;
+// Cells per side of the grid the importance map is estimated on
+const IMPORTANCE_GRID: usize = 256;
+// Weight of the cells which looked uninteresting, keeps the sampling unbiased
+const IMPORTANCE_FLOOR: f32 = 0.02;
+// Share of the densest pixels which clip to white
+const TONE_PERCENTILE: f64 = 0.995;
+const DEFAULT_GAMMA: f32 = 2.;
+
+/// Renders the density of escaping orbits instead of coloring each pixel by its own orbit.
+/// Each RGB channel only counts orbits which escaped within its own limit,
+/// the same limit everywhere gives a Buddhabrot, different ones a Nebulabrot.
+pub struct Buddhabrot {
+    params: MandelbrotParameters,
+    /// Iteration limits of the red, green and blue channel
+    pub limits:     [u32;3],
+    pub samples:    u64,
+    /// Spend samples where orbits reach the view, instead of uniformly
+    pub importance: bool,
+    pub gamma:      f32,
+}
+
+impl Buddhabrot {
+    pub fn new(params: MandelbrotParameters) -> Self {
+        Self {
+            limits:     [params.max_iter;3],
+            samples:    (params.width*params.height) as u64 * SAMPLES_PER_PIXEL,
+            importance: true,
+            gamma:      DEFAULT_GAMMA,
+            params,
+        }
+    }
+
+    pub fn render(&self) -> Vec<u8> {
+        let importance = match self.importance {
+            true  => Importance::new(self),
+            false => Importance::uniform(),
+        };
+
+        let pixel_count = (self.params.width*self.params.height) as usize;
+        let chunks = self.samples.div_ceil(CHUNK_SIZE);
+        // Every rayon task keeps its own buffer, they are summed at the end
+        let density = (0..chunks).into_par_iter()
+            .fold(|| vec![0f32; pixel_count*3], |mut density, chunk| {
+                let mut random = Random::new(chunk);
+                let mut orbit  = Vec::new();
+                let samples = CHUNK_SIZE.min(self.samples - chunk*CHUNK_SIZE);
+                for _ in 0..samples {
+                    let (c, weight) = importance.sample(&mut random);
+                    self.accumulate(c, weight, &mut orbit, &mut density);
+                }
+                density
+            })
+            .reduce(|| vec![0f32; pixel_count*3], |mut a, b| {
+                a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                a
+            });
+
+        self.tone_map(&density)
+    }
+
+    /// Adds the orbit of c to every channel it escaped in time for
+    fn accumulate(&self, c: (f64,f64), weight: f32, orbit: &mut Vec<(f64,f64)>, density: &mut [f32]) {
+        let limit = self.limits.iter().copied().max().unwrap_or(0);
+        if !self.escapes(c, limit, orbit) {
+            return;
+        }
+
+        for point in orbit.iter() {
+            let i = match self.pixel(*point) {
+                Some(i) => i,
+                None    => continue,
+            };
+            for (channel, channel_limit) in self.limits.iter().enumerate() {
+                if orbit.len() <= *channel_limit as usize {
+                    density[i*3 + channel] += weight;
+                }
+            }
+        }
+    }
+
+    /// Iterates c, keeping the orbit, returns whether it escaped within `limit`
+    fn escapes(&self, c: (f64,f64), limit: u32, orbit: &mut Vec<(f64,f64)>) -> bool {
+        orbit.clear();
+        if self.params.formula.is_mandelbrot() && Mandelbrot::known_interior(c.0, c.1).is_some() {
+            return false;
+        }
+
+        let escape = self.params.escape_radius()*self.params.escape_radius();
+        let mut z = (0., 0.);
+        for _ in 0..limit {
+            z = self.params.formula.step(z, c);
+            orbit.push(z);
+            if z.0*z.0 + z.1*z.1 > escape {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Index of the pixel the point falls into, if it's in the view at all
+    fn pixel(&self, point: (f64,f64)) -> Option<usize> {
+        let center  = self.params.view.center_f64();
+        let extent  = self.params.extent();
+        let spacing = self.params.pixel_spacing();
+        let offset  = self.params.transform.invert((point.0 - center.0, point.1 - center.1));
+        let x = (offset.0 + extent.0) / spacing;
+        let y = (offset.1 + extent.1) / spacing;
+        if x < 0. || y < 0. || x >= self.params.width as f64 || y >= self.params.height as f64 {
+            return None;
+        }
+        Some(y as usize * self.params.width as usize + x as usize)
+    }
+
+    /// Scales every channel so a high percentile of it is white, then applies the gamma
+    fn tone_map(&self, density: &[f32]) -> Vec<u8> {
+        let mut scale = [0f32;3];
+        for (channel, scale) in scale.iter_mut().enumerate() {
+            let mut values = density.iter().skip(channel).step_by(3)
+                .copied()
+                .filter(|&value| value > 0.)
+                .collect::<Vec<f32>>();
+            if values.is_empty() {
+                continue;
+            }
+            let nth = ((values.len()-1) as f64 * TONE_PERCENTILE) as usize;
+            let (_, white, _) = values.select_nth_unstable_by(nth, |a, b| a.total_cmp(b));
+            *scale = 1. / *white;
+        }
+
+        density.par_chunks(3).flat_map_iter(|pixel| {
+            (0..3).map(move |channel| {
+                let value = (pixel[channel]*scale[channel]).min(1.);
+                (value.powf(1. / self.gamma) * 255.) as u8
+            })
+        }).collect()
+    }
+}
+
+impl Compute for Buddhabrot {
+    fn compute(&mut self) -> Vec<u8> {
+        self.render()
+    }
+}
+
+/// Probability of sampling c in every cell of a coarse grid,
+/// orbits are weighted by the inverse so the density stays the same
+struct Importance {
+    cumulative: Vec<f32>,
+    weights:    Vec<f32>,
+}
+
+impl Importance {
+    fn uniform() -> Self {
+        Self {
+            cumulative: vec![1.],
+            weights:    vec![1.],
+        }
+    }
+
+    fn new(buddhabrot: &Buddhabrot) -> Self {
+        let cell  = 2.*SAMPLE_RADIUS / IMPORTANCE_GRID as f64;
+        let limit = buddhabrot.limits.iter().copied().max().unwrap_or(0);
+
+        // Cells whose center escapes with an orbit through the view
+        let hits = (0..IMPORTANCE_GRID*IMPORTANCE_GRID).into_par_iter().map(|i| {
+            let c = (
+                -SAMPLE_RADIUS + ((i % IMPORTANCE_GRID) as f64 + 0.5)*cell,
+                -SAMPLE_RADIUS + ((i / IMPORTANCE_GRID) as f64 + 0.5)*cell,
+            );
+            let mut orbit = Vec::new();
+            buddhabrot.escapes(c, limit, &mut orbit) && orbit.iter().any(|point| buddhabrot.pixel(*point).is_some())
+        }).collect::<Vec<bool>>();
+
+        // Neighbours of a hit are likely to have hits the center missed
+        let n = IMPORTANCE_GRID as isize;
+        let weights = (0..n*n).map(|i| {
+            let (x, y) = (i % n, i / n);
+            let near_hit = (-1..=1).any(|dy| (-1..=1).any(|dx| {
+                let (x, y) = (x+dx, y+dy);
+                x >= 0 && y >= 0 && x < n && y < n && hits[(y*n + x) as usize]
+            }));
+            if near_hit { 1. } else { IMPORTANCE_FLOOR }
+        }).collect::<Vec<f32>>();
+
+        let mut sum = 0.;
+        let cumulative = weights.iter().map(|weight| {
+            sum += weight;
+            sum
+        }).collect();
+
+        Self {
+            cumulative,
+            weights,
+        }
+    }
+
+    /// Random c and the weight its orbit counts with
+    fn sample(&self, random: &mut Random) -> ((f64,f64), f32) {
+        let total = *self.cumulative.last().unwrap();
+        let target = random.next() as f32 * total;
+        let i = self.cumulative.partition_point(|&sum| sum <= target).min(self.weights.len()-1);
+
+        // Cells are laid out row by row over the sampled square
+        let side = (self.weights.len() as f64).sqrt() as usize;
+        let cell = 2.*SAMPLE_RADIUS / side as f64;
+        let c = (
+            -SAMPLE_RADIUS + ((i % side) as f64 + random.next())*cell,
+            -SAMPLE_RADIUS + ((i / side) as f64 + random.next())*cell,
+        );
+        let mean = total / self.weights.len() as f32;
+        (c, mean / self.weights[i])
+    }
+}
+
+/// xorshift64*, good enough for scattering samples and free of dependencies
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        // splitmix64 so neighbouring seeds give unrelated streams
+        let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        Self((z ^ (z >> 31)) | 1)
+    }
+
+    /// Uniform in [0, 1)
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545F4914F6CDD1D) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
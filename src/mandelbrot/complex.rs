@@ -0,0 +1,42 @@
+use super::formula::Real;
+
+// Complex numbers as (re, im) tuples, shared by every backend
+
+pub fn add<T: Real>(a: (T,T), b: (T,T)) -> (T,T) {
+    (a.0+b.0, a.1+b.1)
+}
+
+pub fn sub<T: Real>(a: (T,T), b: (T,T)) -> (T,T) {
+    (a.0-b.0, a.1-b.1)
+}
+
+pub fn mul<T: Real>(a: (T,T), b: (T,T)) -> (T,T) {
+    (a.0*b.0 - a.1*b.1, a.0*b.1 + a.1*b.0)
+}
+
+pub fn div(a: (f64,f64), b: (f64,f64)) -> (f64,f64) {
+    let norm = norm(b);
+    ((a.0*b.0 + a.1*b.1) / norm, (a.1*b.0 - a.0*b.1) / norm)
+}
+
+/// |a|^2
+pub fn norm(a: (f64,f64)) -> f64 {
+    a.0*a.0 + a.1*a.1
+}
+
+pub fn exp(a: (f64,f64)) -> (f64,f64) {
+    let r = a.0.exp();
+    (r*a.1.cos(), r*a.1.sin())
+}
+
+pub fn log(a: (f64,f64)) -> (f64,f64) {
+    (norm(a).ln() / 2., a.1.atan2(a.0))
+}
+
+pub fn sin(a: (f64,f64)) -> (f64,f64) {
+    (a.0.sin()*a.1.cosh(), a.0.cos()*a.1.sinh())
+}
+
+pub fn cos(a: (f64,f64)) -> (f64,f64) {
+    (a.0.cos()*a.1.cosh(), -a.0.sin()*a.1.sinh())
+}
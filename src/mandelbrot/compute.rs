@@ -1,9 +1,13 @@
-use super::Mandelbrot;
-#[cfg(feature = "gpu")]
-use super::MandelbrotParameters;
+use super::{Mandelbrot, MandelbrotParameters, Field};
+use super::formula::Real;
+use super::perturbation::{BigFloat, big_float};
 #[cfg(feature = "gpu")]
 use super::gpu::GpuCompute;
 
+use rayon::prelude::*;
+
+use std::ops::{Add,Sub,Mul,Neg};
+
 #[cfg(feature = "gpu")]
 use std::sync::{Arc,Mutex};
 
@@ -11,6 +15,11 @@ pub trait Compute {
     fn compute(&mut self) -> Vec<u8>;
 }
 
+/// Backends which iterate into a field buffer, it can be colored again without iterating
+pub trait ComputeFields {
+    fn compute_fields(&mut self) -> Vec<Field>;
+}
+
 pub struct ComputeCPU {
     mandelbrot: Mandelbrot,
 }
@@ -30,6 +39,169 @@ impl Compute for ComputeCPU {
     }
 }
 
+impl ComputeFields for ComputeCPU {
+    fn compute_fields(&mut self) -> Vec<Field> {
+        self.mandelbrot.update();
+        self.mandelbrot.fields().to_vec()
+    }
+}
+
+/// Unevaluated sum of two f64, good for about 106 bits of mantissa
+#[derive(Debug, Default, Copy, Clone)]
+pub struct DoubleDouble {
+    hi: f64,
+    lo: f64,
+}
+
+impl DoubleDouble {
+    pub fn from_big(val: &BigFloat) -> Self {
+        let hi = val.to_f64().value();
+        let lo = (val - big_float(hi, val.precision())).to_f64().value();
+        Self{ hi, lo }
+    }
+
+    fn two_sum(a: f64, b: f64) -> Self {
+        let s  = a+b;
+        let bb = s-a;
+        Self{ hi: s, lo: (a-(s-bb)) + (b-bb) }
+    }
+
+    fn quick_two_sum(a: f64, b: f64) -> Self {
+        let s = a+b;
+        Self{ hi: s, lo: b-(s-a) }
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        let s = Self::two_sum(self.hi, rhs.hi);
+        let t = Self::two_sum(self.lo, rhs.lo);
+        let s = Self::quick_two_sum(s.hi, s.lo+t.hi);
+        Self::quick_two_sum(s.hi, s.lo+t.lo)
+    }
+}
+
+impl Add<f64> for DoubleDouble {
+    type Output = Self;
+    fn add(self, rhs: f64) -> Self {
+        let s = Self::two_sum(self.hi, rhs);
+        Self::quick_two_sum(s.hi, s.lo+self.lo)
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        self + -rhs
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self{ hi: -self.hi, lo: -self.lo }
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        let p   = self.hi*rhs.hi;
+        let err = self.hi.mul_add(rhs.hi, -p);
+        Self::quick_two_sum(p, err + (self.hi*rhs.lo + self.lo*rhs.hi))
+    }
+}
+
+impl Mul<f64> for DoubleDouble {
+    type Output = Self;
+    fn mul(self, rhs: f64) -> Self {
+        let p   = self.hi*rhs;
+        let err = self.hi.mul_add(rhs, -p);
+        Self::quick_two_sum(p, err + self.lo*rhs)
+    }
+}
+
+impl Real for DoubleDouble {
+    fn from_f64(val: f64) -> Self {
+        Self{ hi: val, lo: 0. }
+    }
+
+    fn to_f64(self) -> f64 {
+        self.hi
+    }
+
+    fn abs(self) -> Self {
+        if self.hi < 0. { -self } else { self }
+    }
+}
+
+/// Renders with double-double arithmetic,
+/// for views past the f64 limit which aren't yet deep enough for perturbation
+pub struct ComputeDoubleDouble {
+    params: MandelbrotParameters,
+}
+
+impl ComputeDoubleDouble {
+    pub fn new(params: MandelbrotParameters) -> Self {
+        Self{
+            params,
+        }
+    }
+
+    pub fn render(params: &MandelbrotParameters, fields: &mut [Field]) {
+        let center = params.view.center_binary(106);
+        let center = (DoubleDouble::from_big(&center.0), DoubleDouble::from_big(&center.1));
+
+        fields.par_iter_mut().enumerate().for_each(|(i, field)| {
+            *field = Self::compute(params, center, i as u32);
+        });
+    }
+
+    fn compute(params: &MandelbrotParameters, center: (DoubleDouble, DoubleDouble), i: u32) -> Field {
+        let offset = params.pixel_offset(i);
+        let x = center.0 + offset.0;
+        let y = center.1 + offset.1;
+
+        let (mut z, c) = match params.julia_c {
+            Some(c) => ((x, y), (DoubleDouble::from_f64(c.0), DoubleDouble::from_f64(c.1))),
+            None    => ((DoubleDouble::default(), DoubleDouble::default()), (x, y)),
+        };
+        // The derivative doesn't need the extra precision
+        let (_, _, mut d) = params.start((x.hi, y.hi));
+
+        let escape  = params.escape_radius()*params.escape_radius();
+        let spacing = params.pixel_spacing();
+        let dc = if params.julia_c.is_some() { 0. } else { spacing };
+
+        let mut iter = 0;
+        while z.0.hi*z.0.hi+z.1.hi*z.1.hi <= escape && iter < params.max_iter {
+            d = params.formula.derivative((z.0.hi, z.1.hi), (c.0.hi, c.1.hi), d, dc);
+            z = params.formula.step(z, c);
+            iter += 1;
+        }
+
+        Field::new(params, iter, (z.0.hi, z.1.hi), d)
+    }
+}
+
+impl ComputeFields for ComputeDoubleDouble {
+    fn compute_fields(&mut self) -> Vec<Field> {
+        let mut fields = vec![Field::default();(self.params.width*self.params.height) as usize];
+        Self::render(&self.params, &mut fields);
+        fields
+    }
+}
+
+impl Compute for ComputeDoubleDouble {
+    fn compute(&mut self) -> Vec<u8> {
+        let fields = self.compute_fields();
+        let mut pixels = vec![0u8;fields.len()*3];
+        Mandelbrot::color_fields(&self.params, &fields, &mut pixels);
+        pixels
+    }
+}
+
 #[cfg(feature = "gpu")]
 pub struct ComputeGPU {
     params: MandelbrotParameters,
@@ -46,9 +218,70 @@ impl ComputeGPU {
     }
 }
 
+#[cfg(feature = "gpu")]
+impl ComputeFields for ComputeGPU {
+    fn compute_fields(&mut self) -> Vec<Field> {
+        let mut gpu_compute = self.gpu_compute.lock().unwrap();
+        if gpu_compute.fits((self.params.width*self.params.height) as usize) {
+            return gpu_compute.compute(&self.params).unwrap();
+        }
+        drop(gpu_compute);
+
+        // Too large for the gpu's buffer, the cpu has no such limit
+        let mut mandelbrot = Mandelbrot::builder(self.params.width, self.params.height)
+            .on_gpu(false)
+            .build();
+        *mandelbrot.params_mut() = self.params.clone();
+        mandelbrot.update_fields();
+        mandelbrot.fields
+    }
+}
+
 #[cfg(feature = "gpu")]
 impl Compute for ComputeGPU {
     fn compute(&mut self) -> Vec<u8> {
-        self.gpu_compute.lock().unwrap().compute(&self.params).unwrap().iter().map(|x| *x as u8).collect::<Vec<u8>>()
+        let fields = self.compute_fields();
+        let mut pixels = vec![0u8;fields.len()*3];
+        Mandelbrot::color_fields(&self.params, &fields, &mut pixels);
+        pixels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRECISION: usize = 200;
+
+    fn exact(val: DoubleDouble) -> BigFloat {
+        big_float(val.hi, PRECISION) + big_float(val.lo, PRECISION)
+    }
+
+    fn fraction(num: f64, den: f64) -> BigFloat {
+        big_float(num, PRECISION) / big_float(den, PRECISION)
+    }
+
+    // Within a few units of the last of the 106 bits
+    fn assert_close(result: DoubleDouble, expected: BigFloat) {
+        let error = (exact(result) - &expected).to_f64().value().abs();
+        assert!(error <= expected.to_f64().value().abs() * 2f64.powi(-104), "{:?} is off by {:e}", result, error);
+    }
+
+    #[test]
+    fn error_terms() {
+        let pairs = [
+            (fraction(1., 3.), fraction(2., 7.)),
+            (fraction(-5., 11.), fraction(1e-10, 3.)),
+            (fraction(123456789., 7.), fraction(-1., 13.)),
+        ];
+        for (a, b) in pairs.iter() {
+            let (x, y) = (DoubleDouble::from_big(a), DoubleDouble::from_big(b));
+            assert_close(x + y, exact(x) + exact(y));
+            assert_close(x * y, exact(x) * exact(y));
+            assert_close(x + y.hi, exact(x) + big_float(y.hi, PRECISION));
+            assert_close(x * y.hi, exact(x) * big_float(y.hi, PRECISION));
+            // The low part is what plain f64 would have dropped
+            assert!(x.lo != 0. && (x * y).lo != 0.);
+        }
     }
 }
@@ -0,0 +1,544 @@
+use std::{fmt, str::FromStr};
+
+use super::complex::{add, sub, mul, div, exp, log, sin, cos};
+
+// Integer powers up to this are expanded into multiplications
+const MAX_INTEGER_POWER: f64 = 64.;
+
+/// Complex number together with its derivative along some direction,
+/// evaluating a formula on these gives the formula's derivative for free
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Dual {
+    pub v: (f64,f64),
+    pub d: (f64,f64),
+}
+
+impl Dual {
+    pub fn new(v: (f64,f64), d: (f64,f64)) -> Self {
+        Self{ v, d }
+    }
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(add(self.v, rhs.v), add(self.d, rhs.d))
+    }
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(sub(self.v, rhs.v), sub(self.d, rhs.d))
+    }
+
+    fn neg(self) -> Self {
+        Self::new((-self.v.0, -self.v.1), (-self.d.0, -self.d.1))
+    }
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(mul(self.v, rhs.v), add(mul(self.d, rhs.v), mul(self.v, rhs.d)))
+    }
+
+    fn div(self, rhs: Self) -> Self {
+        let v = div(self.v, rhs.v);
+        // (a'b - ab') / b^2
+        let d = div(sub(mul(self.d, rhs.v), mul(self.v, rhs.d)), mul(rhs.v, rhs.v));
+        Self::new(v, d)
+    }
+
+    fn powi(self, n: i32) -> Self {
+        if n < 0 {
+            return Self::new((1., 0.), (0., 0.)).div(self.powi(-n));
+        }
+        let mut v    = (1., 0.);
+        let mut prev = (1., 0.);
+        for _ in 0..n {
+            prev = v;
+            v    = mul(v, self.v);
+        }
+        // n*a^(n-1)*a'
+        let d = if n == 0 { (0., 0.) } else { mul(mul((n as f64, 0.), prev), self.d) };
+        Self::new(v, d)
+    }
+
+    fn pow(self, rhs: Self) -> Self {
+        if self.v == (0., 0.) {
+            return Self::default();
+        }
+        // a^b = exp(b*log(a))
+        self.log().mul(rhs).exp()
+    }
+
+    fn exp(self) -> Self {
+        let v = exp(self.v);
+        Self::new(v, mul(v, self.d))
+    }
+
+    fn log(self) -> Self {
+        Self::new(log(self.v), div(self.d, self.v))
+    }
+
+    fn sin(self) -> Self {
+        Self::new(sin(self.v), mul(cos(self.v), self.d))
+    }
+
+    fn cos(self) -> Self {
+        let s = sin(self.v);
+        Self::new(cos(self.v), mul((-s.0, -s.1), self.d))
+    }
+
+    /// Folds both parts to positive like the Burning Ship does
+    fn abs(self) -> Self {
+        let (sx, sy) = (self.v.0.signum(), self.v.1.signum());
+        Self::new((self.v.0.abs(), self.v.1.abs()), (sx*self.d.0, sy*self.d.1))
+    }
+
+    fn conj(self) -> Self {
+        Self::new((self.v.0, -self.v.1), (self.d.0, -self.d.1))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Function {
+    Exp,
+    Log,
+    Sin,
+    Cos,
+    Abs,
+    Conj,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "exp"  => Some(Function::Exp),
+            "log"  => Some(Function::Log),
+            "sin"  => Some(Function::Sin),
+            "cos"  => Some(Function::Cos),
+            "abs"  => Some(Function::Abs),
+            "conj" => Some(Function::Conj),
+            _ => None,
+        }
+    }
+
+    fn apply(self, a: Dual) -> Dual {
+        match self {
+            Function::Exp  => a.exp(),
+            Function::Log  => a.log(),
+            Function::Sin  => a.sin(),
+            Function::Cos  => a.cos(),
+            Function::Abs  => a.abs(),
+            Function::Conj => a.conj(),
+        }
+    }
+
+    #[cfg(feature = "gpu")]
+    fn wgsl(self) -> &'static str {
+        match self {
+            Function::Exp  => "dual_exp",
+            Function::Log  => "dual_log",
+            Function::Sin  => "dual_sin",
+            Function::Cos  => "dual_cos",
+            Function::Abs  => "dual_abs",
+            Function::Conj => "dual_conj",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Z,
+    C,
+    Constant((f64,f64)),
+    Neg(Box<Node>),
+    Call(Function, Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    /// The exponent if it's a small real integer, those are expanded into multiplications
+    fn integer(&self) -> Option<i32> {
+        match self {
+            Node::Constant((re, im)) if *im == 0. && re.fract() == 0. && re.abs() <= MAX_INTEGER_POWER => Some(*re as i32),
+            Node::Neg(a) => a.integer().map(|n| -n),
+            _ => None,
+        }
+    }
+
+    fn compile(&self) -> Compiled {
+        match self {
+            Node::Z => Box::new(|z, _| z),
+            Node::C => Box::new(|_, c| c),
+            Node::Constant(v) => {
+                let v = Dual::new(*v, (0., 0.));
+                Box::new(move |_, _| v)
+            },
+            Node::Neg(a) => {
+                let a = a.compile();
+                Box::new(move |z, c| a(z, c).neg())
+            },
+            Node::Call(function, a) => {
+                let (function, a) = (*function, a.compile());
+                Box::new(move |z, c| function.apply(a(z, c)))
+            },
+            Node::Binary(Operator::Pow, a, b) if b.integer().is_some() => {
+                let (a, n) = (a.compile(), b.integer().unwrap());
+                Box::new(move |z, c| a(z, c).powi(n))
+            },
+            Node::Binary(op, a, b) => {
+                let (a, b) = (a.compile(), b.compile());
+                match op {
+                    Operator::Add => Box::new(move |z, c| a(z, c).add(b(z, c))),
+                    Operator::Sub => Box::new(move |z, c| a(z, c).sub(b(z, c))),
+                    Operator::Mul => Box::new(move |z, c| a(z, c).mul(b(z, c))),
+                    Operator::Div => Box::new(move |z, c| a(z, c).div(b(z, c))),
+                    Operator::Pow => Box::new(move |z, c| a(z, c).pow(b(z, c))),
+                }
+            },
+        }
+    }
+
+    #[cfg(feature = "gpu")]
+    fn wgsl(&self) -> String {
+        match self {
+            Node::Z => "z".to_string(),
+            Node::C => "c".to_string(),
+            Node::Constant((re, im)) => format!("dual_constant({:?}f32, {:?}f32)", *re as f32, *im as f32),
+            Node::Neg(a) => format!("dual_neg({})", a.wgsl()),
+            Node::Call(function, a) => format!("{}({})", function.wgsl(), a.wgsl()),
+            Node::Binary(Operator::Pow, a, b) if b.integer().is_some() => {
+                format!("dual_powi({}, {})", a.wgsl(), b.integer().unwrap())
+            },
+            Node::Binary(op, a, b) => {
+                let name = match op {
+                    Operator::Add => "dual_add",
+                    Operator::Sub => "dual_sub",
+                    Operator::Mul => "dual_mul",
+                    Operator::Div => "dual_div",
+                    Operator::Pow => "dual_pow",
+                };
+                format!("{}({}, {})", name, a.wgsl(), b.wgsl())
+            },
+        }
+    }
+
+    /// Polynomial degree in z, None if it isn't a polynomial
+    fn degree(&self) -> Option<f64> {
+        match self {
+            Node::Z => Some(1.),
+            Node::C | Node::Constant(_) => Some(0.),
+            Node::Neg(a) => a.degree(),
+            Node::Call(Function::Conj, a) | Node::Call(Function::Abs, a) => a.degree(),
+            Node::Call(..) => None,
+            Node::Binary(Operator::Add, a, b) | Node::Binary(Operator::Sub, a, b) => Some(a.degree()?.max(b.degree()?)),
+            Node::Binary(Operator::Mul, a, b) => Some(a.degree()? + b.degree()?),
+            Node::Binary(Operator::Div, a, b) if b.degree()? == 0. => a.degree(),
+            Node::Binary(Operator::Pow, a, b) => match b.as_ref() {
+                Node::Constant((re, im)) if *im == 0. && *re >= 0. => Some(a.degree()? * re),
+                _ => None,
+            },
+            Node::Binary(..) => None,
+        }
+    }
+}
+
+type Compiled = Box<dyn Fn(Dual, Dual) -> Dual + Send + Sync>;
+
+/// User formula for the next z, e.g. `z = z^3 - 0.5*z + c`.
+/// Knows `z`, `c`, `i`, `pi`, `+ - * / ^`, and the functions exp, log, sin, cos, abs and conj.
+/// `abs` is component-wise, it folds the real and imaginary parts to positive like the Burning Ship
+/// and doesn't give the modulus.
+pub struct Expression {
+    source:   String,
+    root:     Node,
+    compiled: Compiled,
+}
+
+impl Expression {
+    /// Next z and its derivative, with `z` and `c` carrying the derivatives they start with
+    pub fn eval(&self, z: Dual, c: Dual) -> Dual {
+        (self.compiled)(z, c)
+    }
+
+    /// The same expression for the shader, evaluates to a `Dual` of `z` and `c`
+    #[cfg(feature = "gpu")]
+    pub fn wgsl(&self) -> String {
+        self.root.wgsl()
+    }
+
+    /// How fast |z| grows once it's large, 2 if the expression isn't a polynomial in z
+    pub fn degree(&self) -> f64 {
+        match self.root.degree() {
+            Some(degree) if degree > 1. => degree,
+            _ => 2.,
+        }
+    }
+}
+
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        self.root == other.root
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "z = {}", self.source)
+    }
+}
+
+impl fmt::Debug for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Expression({})", self)
+    }
+}
+
+impl FromStr for Expression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // The assignment is optional
+        let source = match s.split_once('=') {
+            Some((lhs, rhs)) if lhs.trim() == "z" => rhs,
+            Some(_) => return Err("Only z can be assigned to".to_string()),
+            None    => s,
+        }.trim();
+
+        let mut parser = Parser{ tokens: tokenize(source)?, pos: 0 };
+        let root = parser.expression()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected '{}'", token));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            compiled: root.compile(),
+            root,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Symbol(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Name(s)   => write!(f, "{}", s),
+            Token::Symbol(c) => write!(f, "{}", c),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let chars = s.chars().collect::<Vec<char>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        if ch.is_whitespace() {
+            i += 1;
+        } else if ch.is_ascii_digit() || ch == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.'
+                || ((chars[i] == 'e' || chars[i] == 'E') && i+1 < chars.len() && (chars[i+1].is_ascii_digit() || chars[i+1] == '-'))
+                || (chars[i] == '-' && (chars[i-1] == 'e' || chars[i-1] == 'E'))) {
+                i += 1;
+            }
+            let number = chars[start..i].iter().collect::<String>();
+            tokens.push(Token::Number(number.parse().map_err(|_| format!("Invalid number '{}'", number))?));
+        } else if ch.is_alphabetic() {
+            let start = i;
+            while i < chars.len() && chars[i].is_alphanumeric() {
+                i += 1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect()));
+        } else if "+-*/^()".contains(ch) {
+            tokens.push(Token::Symbol(ch));
+            i += 1;
+        } else {
+            return Err(format!("Unexpected '{}'", ch));
+        }
+    }
+    Ok(tokens)
+}
+
+// Recursive descent, from the lowest precedence up
+struct Parser {
+    tokens: Vec<Token>,
+    pos:    usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    // sum := product (('+' | '-') product)*
+    fn expression(&mut self) -> Result<Node, String> {
+        let mut node = self.product()?;
+        loop {
+            let op = if self.eat('+') {
+                Operator::Add
+            } else if self.eat('-') {
+                Operator::Sub
+            } else {
+                return Ok(node);
+            };
+            node = Node::Binary(op, Box::new(node), Box::new(self.product()?));
+        }
+    }
+
+    // product := unary (('*' | '/') unary)*
+    fn product(&mut self) -> Result<Node, String> {
+        let mut node = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                Operator::Mul
+            } else if self.eat('/') {
+                Operator::Div
+            } else {
+                return Ok(node);
+            };
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+    }
+
+    // unary := '-' unary | power
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.eat('-') {
+            return Ok(Node::Neg(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    // power := atom ('^' unary)?, right associative
+    fn power(&mut self) -> Result<Node, String> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            return Ok(Node::Binary(Operator::Pow, Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    // atom := number | 'z' | 'c' | 'i' | 'pi' | function '(' sum ')' | '(' sum ')'
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.next() {
+            // The shader only has f32
+            Some(Token::Number(n)) if !(n as f32).is_finite() => Err(format!("'{}' is too large", n)),
+            Some(Token::Number(n)) => Ok(Node::Constant((n, 0.))),
+            Some(Token::Symbol('(')) => {
+                let node = self.expression()?;
+                if !self.eat(')') {
+                    return Err("Missing ')'".to_string());
+                }
+                Ok(node)
+            },
+            Some(Token::Name(name)) => match name.as_str() {
+                "z"  => Ok(Node::Z),
+                "c"  => Ok(Node::C),
+                "i"  => Ok(Node::Constant((0., 1.))),
+                "pi" => Ok(Node::Constant((std::f64::consts::PI, 0.))),
+                _ => {
+                    let function = Function::from_name(&name).ok_or_else(|| format!("Unknown name '{}'", name))?;
+                    if !self.eat('(') {
+                        return Err(format!("Expected '(' after '{}'", name));
+                    }
+                    let node = self.expression()?;
+                    if !self.eat(')') {
+                        return Err("Missing ')'".to_string());
+                    }
+                    Ok(Node::Call(function, Box::new(node)))
+                },
+            },
+            Some(token) => Err(format!("Unexpected '{}'", token)),
+            None => Err("Unexpected end of formula".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(formula: &str, z: (f64,f64), c: (f64,f64)) -> (f64,f64) {
+        let expression = formula.parse::<Expression>().unwrap();
+        expression.eval(Dual::new(z, (0., 0.)), Dual::new(c, (0., 0.))).v
+    }
+
+    fn assert_close(a: (f64,f64), b: (f64,f64)) {
+        assert!((a.0-b.0).abs() < 1e-9 && (a.1-b.1).abs() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    fn error(formula: &str) -> String {
+        formula.parse::<Expression>().unwrap_err()
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(eval("1.5", (0., 0.), (0., 0.)), (1.5, 0.));
+        assert_eq!(eval(".25", (0., 0.), (0., 0.)), (0.25, 0.));
+        assert_eq!(eval("2e3", (0., 0.), (0., 0.)), (2000., 0.));
+        assert_eq!(eval("2E-2", (0., 0.), (0., 0.)), (0.02, 0.));
+        // The minus after the exponent belongs to the number, the one before it doesn't
+        assert_close(eval("1e-1-1", (0., 0.), (0., 0.)), (-0.9, 0.));
+        assert_eq!(eval("2*i", (0., 0.), (0., 0.)), (0., 2.));
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2*3", (0., 0.), (0., 0.)), (7., 0.));
+        assert_eq!(eval("(1 + 2)*3", (0., 0.), (0., 0.)), (9., 0.));
+        assert_eq!(eval("8/2/2", (0., 0.), (0., 0.)), (2., 0.));
+        assert_eq!(eval("5-2-1", (0., 0.), (0., 0.)), (2., 0.));
+        assert_eq!(eval("2*z^2 + c", (3., 0.), (1., 0.)), (19., 0.));
+        // Unary minus binds looser than ^
+        assert_eq!(eval("-z^2", (3., 0.), (0., 0.)), (-9., 0.));
+        assert_eq!(eval("z = z^2 + c", (0., 1.), (0.5, 0.)), (-0.5, 0.));
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        // 3^2 is expanded into multiplications, 2^9 goes through exp and log
+        assert_close(eval("2^3^2", (0., 0.), (0., 0.)), (512., 0.));
+        assert_eq!(eval("2^-1", (0., 0.), (0., 0.)), (0.5, 0.));
+        assert_eq!("z^2^3".parse::<Expression>(), "z^(2^3)".parse::<Expression>());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("z^"),         "Unexpected end of formula");
+        assert_eq!(error("(z + c"),     "Missing ')'");
+        assert_eq!(error("z + c)"),     "Unexpected ')'");
+        assert_eq!(error("w + c"),      "Unknown name 'w'");
+        assert_eq!(error("2i"),         "Unexpected 'i'");
+        assert_eq!(error("sin z"),      "Expected '(' after 'sin'");
+        assert_eq!(error("z % 2"),      "Unexpected '%'");
+        assert_eq!(error("c = z"),      "Only z can be assigned to");
+        assert_eq!(error("1.2.3"),      "Invalid number '1.2.3'");
+        assert_eq!(error("z^2 + 1e39"), "'1000000000000000000000000000000000000000' is too large");
+    }
+}
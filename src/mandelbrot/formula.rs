@@ -0,0 +1,211 @@
+use std::{fmt, str::FromStr, sync::Arc, ops::{Add,Sub,Mul,Neg}};
+
+use super::expression::{Dual, Expression};
+use super::complex::mul;
+
+/// Number type the formulas can be iterated in
+pub trait Real: Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Neg<Output = Self> {
+    fn from_f64(val: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn abs(self) -> Self;
+}
+
+impl Real for f64 {
+    fn from_f64(val: f64) -> Self { val }
+    fn to_f64(self) -> f64 { self }
+    fn abs(self) -> Self { f64::abs(self) }
+}
+
+/// Escape-time iteration `z -> f(z) + c`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Formula {
+    /// z^n + c, the Mandelbrot set for n = 2
+    Power(u32),
+    /// z^p + c for a real p > 1, always iterated in f64
+    RealPower(f64),
+    /// (|Re z| + i|Im z|)^2 + c
+    BurningShip,
+    /// conj(z)^2 + c, also known as the Mandelbar
+    Tricorn,
+    /// |Re z^2| + i Im z^2 + c
+    Celtic,
+    /// |Re z^2| + i|Im z^2| + c
+    Buffalo,
+    /// Typed in by the user, c has to be added by the expression itself.
+    /// Always iterated in f64.
+    Custom(Arc<Expression>),
+}
+
+impl Default for Formula {
+    fn default() -> Self {
+        Formula::Power(2)
+    }
+}
+
+impl Formula {
+    /// Next formula for switching through them at runtime
+    pub fn next(&self) -> Self {
+        match *self {
+            Formula::Power(n) if n < 4 => Formula::Power(n+1),
+            Formula::Power(_)     => Formula::RealPower(2.5),
+            Formula::RealPower(_) => Formula::BurningShip,
+            Formula::BurningShip  => Formula::Tricorn,
+            Formula::Tricorn      => Formula::Celtic,
+            Formula::Celtic       => Formula::Buffalo,
+            Formula::Buffalo      => Formula::Power(2),
+            Formula::Custom(_)    => Formula::Power(2),
+        }
+    }
+
+    pub fn is_mandelbrot(&self) -> bool {
+        *self == Formula::Power(2)
+    }
+
+    /// How fast |z| grows once it's large, the base of the smooth coloring's logarithm
+    pub fn degree(&self) -> f64 {
+        match self {
+            Formula::Power(n)     => *n as f64,
+            Formula::RealPower(p) => *p,
+            Formula::Custom(expression) => expression.degree(),
+            _ => 2.,
+        }
+    }
+
+    /// Index of the code path in the shader
+    #[cfg(feature = "gpu")]
+    pub fn shader_id(&self) -> u32 {
+        match self {
+            Formula::Power(_)     => 0,
+            Formula::RealPower(_) => 1,
+            Formula::BurningShip  => 2,
+            Formula::Tricorn      => 3,
+            Formula::Celtic       => 4,
+            Formula::Buffalo      => 5,
+            Formula::Custom(_)    => 6,
+        }
+    }
+
+    pub fn step<T: Real>(&self, z: (T,T), c: (T,T)) -> (T,T) {
+        let z = match self {
+            Formula::Power(n) => {
+                let mut w = z;
+                for _ in 1..*n {
+                    w = mul(w, z);
+                }
+                w
+            },
+            Formula::RealPower(p) => {
+                let w = real_pow((z.0.to_f64(), z.1.to_f64()), *p);
+                (T::from_f64(w.0), T::from_f64(w.1))
+            },
+            Formula::Custom(expression) => {
+                let z = Dual::new((z.0.to_f64(), z.1.to_f64()), (0., 0.));
+                let c = Dual::new((c.0.to_f64(), c.1.to_f64()), (0., 0.));
+                let w = expression.eval(z, c).v;
+                return (T::from_f64(w.0), T::from_f64(w.1));
+            },
+            Formula::BurningShip => square((z.0.abs(), z.1.abs())),
+            Formula::Tricorn     => square((z.0, -z.1)),
+            Formula::Celtic      => { let w = square(z); (w.0.abs(), w.1) },
+            Formula::Buffalo     => { let w = square(z); (w.0.abs(), w.1.abs()) },
+        };
+        (z.0 + c.0, z.1 + c.1)
+    }
+
+    /// Derivative of `step` applied to `d`, with c moving by `dc` along the real axis.
+    /// The folding formulas aren't holomorphic, for them it's the derivative along the real axis.
+    pub fn derivative(&self, z: (f64,f64), c: (f64,f64), d: (f64,f64), dc: f64) -> (f64,f64) {
+        let w = match self {
+            Formula::Power(n) => {
+                let mut w = (*n as f64, 0.);
+                for _ in 1..*n {
+                    w = mul(w, z);
+                }
+                mul(w, d)
+            },
+            Formula::RealPower(p) => {
+                if z == (0., 0.) {
+                    (0., 0.)
+                } else {
+                    let w = real_pow(z, p-1.);
+                    mul((p*w.0, p*w.1), d)
+                }
+            },
+            Formula::BurningShip => {
+                let (sx, sy) = (z.0.signum(), z.1.signum());
+                mul((2.*sx*z.0, 2.*sy*z.1), (sx*d.0, sy*d.1))
+            },
+            Formula::Tricorn => {
+                let w = mul((2.*z.0, 2.*z.1), d);
+                (w.0, -w.1)
+            },
+            Formula::Celtic => {
+                let w = mul((2.*z.0, 2.*z.1), d);
+                ((z.0*z.0 - z.1*z.1).signum()*w.0, w.1)
+            },
+            Formula::Buffalo => {
+                let w = mul((2.*z.0, 2.*z.1), d);
+                ((z.0*z.0 - z.1*z.1).signum()*w.0, (z.0*z.1).signum()*w.1)
+            },
+            Formula::Custom(expression) => {
+                return expression.eval(Dual::new(z, d), Dual::new(c, (dc, 0.))).d;
+            },
+        };
+        (w.0 + dc, w.1)
+    }
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Formula::Power(2)     => write!(f, "mandelbrot"),
+            Formula::Power(n)     => write!(f, "power {}", n),
+            Formula::RealPower(p) => write!(f, "power {}", p),
+            Formula::BurningShip  => write!(f, "burning ship"),
+            Formula::Tricorn      => write!(f, "tricorn"),
+            Formula::Celtic       => write!(f, "celtic"),
+            Formula::Buffalo      => write!(f, "buffalo"),
+            Formula::Custom(expression) => write!(f, "{}", expression),
+        }
+    }
+}
+
+/// Parses the names `Display` writes, anything else is taken as a custom formula
+impl FromStr for Formula {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s {
+            "mandelbrot"   => return Ok(Formula::Power(2)),
+            "burning ship" => return Ok(Formula::BurningShip),
+            "tricorn"      => return Ok(Formula::Tricorn),
+            "celtic"       => return Ok(Formula::Celtic),
+            "buffalo"      => return Ok(Formula::Buffalo),
+            _ => {},
+        }
+        if let Some(power) = s.strip_prefix("power ") {
+            let power = power.trim();
+            return match (power.parse::<u32>(), power.parse::<f64>()) {
+                (Ok(n), _) if n >= 2 => Ok(Formula::Power(n)),
+                (_, Ok(p)) if p > 1. => Ok(Formula::RealPower(p)),
+                _ => Err(format!("Invalid power '{}', it has to be above 1", power)),
+            };
+        }
+        s.parse::<Expression>().map(|expression| Formula::Custom(Arc::new(expression)))
+    }
+}
+
+fn square<T: Real>(z: (T,T)) -> (T,T) {
+    let xy = z.0*z.1;
+    (z.0*z.0 - z.1*z.1, xy + xy)
+}
+
+fn real_pow(z: (f64,f64), p: f64) -> (f64,f64) {
+    if z == (0., 0.) {
+        return (0., 0.);
+    }
+    let r     = (z.0*z.0 + z.1*z.1).powf(p/2.);
+    let theta = z.1.atan2(z.0)*p;
+    (r*theta.cos(), r*theta.sin())
+}
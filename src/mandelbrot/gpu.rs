@@ -5,22 +5,33 @@ extern crate pollster;
 use std::borrow::Cow;
 use wgpu::util::DeviceExt;
 
-use super::MandelbrotParameters;
+use super::{MandelbrotParameters, Field, formula::Formula};
 
 use std::path::Path;
 
 const SHADER_PATH: &str = "./shaders/shader.wgsl";
+// Spliced in while no custom formula is in use, the shader only calls it for one
+const DEFAULT_CUSTOM_FORMULA: &str = "dual_add(dual_mul(z, z), c)";
 
+// Has to match MandelbrotParameters in the shader field by field
 #[allow(dead_code)]
+#[repr(C)]
 #[derive(Clone, Copy, Default)]
 struct ShaderParameters{
-	range_min: f32,
-	range_max: f32,
+	range_x:   f32,
+	range_y:   f32,
 	pos_x:     f32,
 	pos_y:     f32,
     max_iter:  u32,
     width:     u32,
     height:    u32,
+    bailout:   f32,
+    julia:     u32,
+    julia_x:   f32,
+    julia_y:   f32,
+    formula:   u32,
+    degree:    f32,
+    transform: [f32;4],
 }
 
 unsafe impl bytemuck::Zeroable for ShaderParameters {}
@@ -28,53 +39,69 @@ unsafe impl bytemuck::Pod for ShaderParameters {}
 
 impl ShaderParameters {
     pub fn new(params: &MandelbrotParameters) -> Self {
+        let center = params.view.center_f64();
+        let extent = params.extent();
         Self {
-            range_min: params.range.0 as f32,
-            range_max: params.range.1 as f32,
-            pos_x:     params.pos.0   as f32,
-            pos_y:     params.pos.1   as f32,
+            range_x:    extent.0 as f32,
+            range_y:    extent.1 as f32,
+            pos_x:      center.0 as f32,
+            pos_y:      center.1 as f32,
             max_iter:  params.max_iter,
             width:     params.width,
             height:    params.height,
+            bailout:   params.escape_radius() as f32,
+            julia:     params.julia_c.is_some() as u32,
+            julia_x:   params.julia_c.unwrap_or_default().0 as f32,
+            julia_y:   params.julia_c.unwrap_or_default().1 as f32,
+            formula:   params.formula.shader_id(),
+            degree:    params.formula.degree() as f32,
+            transform: params.transform.matrix().map(|m| m as f32),
         }
     }
 }
 
 pub struct GpuCompute {
-    pixel_count_mul: usize,
+    pixel_count: usize,
+    /// Largest storage buffer the adapter can bind, in bytes
+    max_buffer_size: usize,
     device: wgpu::Device,
     queue:  wgpu::Queue,
-    pixels_storage_buffer: wgpu::Buffer,
+    fields_storage_buffer: wgpu::Buffer,
     params_storage_buffer: wgpu::Buffer,
     compute_pipeline: wgpu::ComputePipeline,
     bind_group:       wgpu::BindGroup,
+    custom_formula:   String,
 }
 
 impl GpuCompute {
-    pub fn new(pixel_count_mul: usize) -> Option<Self> {
+    pub fn new(pixel_count: usize) -> Option<Self> {
         let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
         let adapter = pollster::block_on(instance
             .request_adapter(&wgpu::RequestAdapterOptions::default())
             )?;
 
+        // Everything else stays at the defaults, only the field buffer gets as large as the adapter allows
+        let max_buffer_size = adapter.limits().max_storage_buffer_binding_size;
+        if !Self::fits_in(max_buffer_size as usize, pixel_count) {
+            return None;
+        }
+
         let (device, queue) = pollster::block_on(adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
                     features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::downlevel_defaults(),
+                    limits: wgpu::Limits {
+                        max_storage_buffer_binding_size: max_buffer_size,
+                        ..wgpu::Limits::downlevel_defaults()
+                    },
                 },
                 None,
             )).ok()?;
 
-        let cs_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(&Self::get_shader_source(pixel_count_mul/3)?)),
-        });
-
-        let pixels_storage_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Pixel Storage Buffer"),
-            contents: bytemuck::cast_slice(&vec![[0u32,0u32,0u32];pixel_count_mul/3]),
+        let fields_storage_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Field Storage Buffer"),
+            contents: bytemuck::cast_slice(&vec![Field::default();pixel_count]),
             usage: wgpu::BufferUsages::STORAGE
                 | wgpu::BufferUsages::COPY_DST
                 | wgpu::BufferUsages::COPY_SRC,
@@ -87,6 +114,69 @@ impl GpuCompute {
                 | wgpu::BufferUsages::COPY_DST,
         });
 
+        let (compute_pipeline, bind_group) = Self::create_pipeline(
+            &device,
+            &Self::get_shader_source(pixel_count, DEFAULT_CUSTOM_FORMULA)?,
+            &fields_storage_buffer,
+            &params_storage_buffer,
+        );
+
+        Some(Self{
+            pixel_count,
+            max_buffer_size: max_buffer_size as usize,
+            device,
+            queue,
+            fields_storage_buffer,
+            params_storage_buffer,
+            compute_pipeline,
+            bind_group,
+            custom_formula: DEFAULT_CUSTOM_FORMULA.to_string(),
+        })
+    }
+
+    /// The whole image has to fit in a single storage buffer
+    pub fn fits(&self, pixel_count: usize) -> bool {
+        Self::fits_in(self.max_buffer_size, pixel_count)
+    }
+
+    fn fits_in(max_buffer_size: usize, pixel_count: usize) -> bool {
+        pixel_count.checked_mul(std::mem::size_of::<Field>()).is_some_and(|size| size <= max_buffer_size)
+    }
+
+    /// Reallocates the field buffer and rebuilds the shader for another image size, the device is kept
+    fn resize(&mut self, pixel_count: usize) -> Option<()> {
+        if !self.fits(pixel_count) {
+            return None;
+        }
+
+        let fields_storage_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Field Storage Buffer"),
+            size:  (pixel_count * std::mem::size_of::<Field>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let (compute_pipeline, bind_group) = Self::create_pipeline(
+            &self.device,
+            &Self::get_shader_source(pixel_count, &self.custom_formula)?,
+            &fields_storage_buffer,
+            &self.params_storage_buffer,
+        );
+
+        self.pixel_count           = pixel_count;
+        self.fields_storage_buffer = fields_storage_buffer;
+        self.compute_pipeline      = compute_pipeline;
+        self.bind_group            = bind_group;
+        Some(())
+    }
+
+    fn create_pipeline(device: &wgpu::Device, source: &str, fields: &wgpu::Buffer, params: &wgpu::Buffer) -> (wgpu::ComputePipeline, wgpu::BindGroup) {
+        let cs_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+        });
+
         let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
             label: None,
             layout: None,
@@ -101,40 +191,54 @@ impl GpuCompute {
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: pixels_storage_buffer.as_entire_binding(),
+                    resource: fields.as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: params_storage_buffer.as_entire_binding(),
+                    resource: params.as_entire_binding(),
                 },
             ],
         });
 
-        Some(Self{
-            pixel_count_mul,
-            device,
-            queue,
-            pixels_storage_buffer,
-            params_storage_buffer,
-            compute_pipeline,
-            bind_group,
-        })
+        (compute_pipeline, bind_group)
     }
 
-    pub fn compute(&mut self, params: &MandelbrotParameters) -> Option<Vec<u32>> {
+    pub fn compute(&mut self, params: &MandelbrotParameters) -> Option<Vec<Field>> {
+        // The window was resized since the last frame
+        let pixel_count = (params.width*params.height) as usize;
+        if pixel_count != self.pixel_count {
+            self.resize(pixel_count)?;
+        }
+
+        // A new custom formula needs the shader compiled again
+        if let Formula::Custom(expression) = &params.formula {
+            let custom_formula = expression.wgsl();
+            if custom_formula != self.custom_formula {
+                let (compute_pipeline, bind_group) = Self::create_pipeline(
+                    &self.device,
+                    &Self::get_shader_source(self.pixel_count, &custom_formula)?,
+                    &self.fields_storage_buffer,
+                    &self.params_storage_buffer,
+                );
+                self.compute_pipeline = compute_pipeline;
+                self.bind_group       = bind_group;
+                self.custom_formula   = custom_formula;
+            }
+        }
+
         let params = ShaderParameters::new(params);
         self.queue.write_buffer(&self.params_storage_buffer,0,bytemuck::bytes_of(&params));
-        pollster::block_on(self.execute_gpu_inner(self.pixel_count_mul))
+        pollster::block_on(self.execute_gpu_inner(self.pixel_count))
     }
 
-    async fn execute_gpu_inner(&mut self, pixel_count_mul: usize) -> Option<Vec<u32>> {
-        let slice_size = pixel_count_mul * std::mem::size_of::<u32>();
+    async fn execute_gpu_inner(&mut self, pixel_count: usize) -> Option<Vec<Field>> {
+        let slice_size = pixel_count * std::mem::size_of::<Field>();
         let size = slice_size as wgpu::BufferAddress;
 
-        let pixels_size = (std::mem::size_of::<u32>() * self.pixel_count_mul) as wgpu::BufferAddress;
-        let pixels_staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+        let fields_size = (std::mem::size_of::<Field>() * self.pixel_count) as wgpu::BufferAddress;
+        let fields_staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            size:  pixels_size,
+            size:  fields_size,
             usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -146,13 +250,13 @@ impl GpuCompute {
             cpass.set_pipeline(&self.compute_pipeline);
             cpass.set_bind_group(0, &self.bind_group, &[]);
             cpass.insert_debug_marker("Mandelbrot");
-            cpass.dispatch((self.pixel_count_mul/3) as u32, 1, 1); // Number of cells to run, the (x,y,z) size of item being processed
+            cpass.dispatch(self.pixel_count as u32, 1, 1); // Number of cells to run, the (x,y,z) size of item being processed
         }
 
-        encoder.copy_buffer_to_buffer(&self.pixels_storage_buffer, 0, &pixels_staging_buffer, 0, size);
+        encoder.copy_buffer_to_buffer(&self.fields_storage_buffer, 0, &fields_staging_buffer, 0, size);
         self.queue.submit(Some(encoder.finish()));
 
-        let buffer_slice = pixels_staging_buffer.slice(..);
+        let buffer_slice = fields_staging_buffer.slice(..);
         let buffer_future = buffer_slice.map_async(wgpu::MapMode::Read);
 
         self.device.poll(wgpu::Maintain::Wait);
@@ -162,19 +266,23 @@ impl GpuCompute {
             let result = bytemuck::cast_slice(&data).to_vec();
 
             drop(data);
-            pixels_staging_buffer.unmap();
+            fields_staging_buffer.unmap();
             Some(result)
         } else {
             None
         }
     }
 
-    fn get_shader_source(pixel_count: usize) -> Option<String> {
+    fn get_shader_source(pixel_count: usize, custom_formula: &str) -> Option<String> {
         let shader = std::fs::read_to_string(Path::new(SHADER_PATH)).ok()?;
         println!("Replaced for {}", pixel_count);
         Some(shader
             .split(' ')
-            .map(|x| {if x == "PIXEL_COUNT" {(pixel_count).to_string()} else {String::from(x)}})
+            .map(|x| match x {
+                "PIXEL_COUNT"    => pixel_count.to_string(),
+                "CUSTOM_FORMULA" => custom_formula.to_string(),
+                _ => String::from(x),
+            })
             .collect::<Vec<String>>()
             .join(" ")
         )
@@ -0,0 +1,146 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use super::location::Location;
+
+// Oldest locations are forgotten past this many
+const HISTORY_LIMIT: usize = 256;
+const BOOKMARKS_FILE: &str = "mandelbrot/bookmarks.toml";
+
+/// Locations the view came to rest at, for going back and forth between them
+#[derive(Default)]
+pub struct History {
+    past:    Vec<Location>,
+    current: Option<Location>,
+    future:  Vec<Location>,
+}
+
+impl History {
+    /// Records a new location, whatever could have been redone is dropped
+    pub fn push(&mut self, location: Location) {
+        if self.current.as_ref() == Some(&location) {
+            return;
+        }
+        if let Some(current) = self.current.replace(location) {
+            self.past.push(current);
+            if self.past.len() > HISTORY_LIMIT {
+                self.past.remove(0);
+            }
+        }
+        self.future.clear();
+    }
+
+    pub fn undo(&mut self) -> Option<&Location> {
+        let previous = self.past.pop()?;
+        self.future.extend(self.current.replace(previous));
+        self.current.as_ref()
+    }
+
+    pub fn redo(&mut self) -> Option<&Location> {
+        let next = self.future.pop()?;
+        self.past.extend(self.current.replace(next));
+        self.current.as_ref()
+    }
+}
+
+/// Named locations kept in a TOML file, every one is a `[[bookmark]]` with its name next to the location
+/// ```toml
+/// [[bookmark]]
+/// name = "seahorse valley"
+/// view = "-0.75 0.1 0.05"
+/// ...
+/// ```
+pub struct Bookmarks {
+    path:    PathBuf,
+    entries: Vec<(String, Location)>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BookmarksFile {
+    #[serde(default)]
+    bookmark: Vec<Bookmark>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Bookmark {
+    name: String,
+    #[serde(flatten)]
+    location: Location,
+}
+
+impl Bookmarks {
+    /// `bookmarks.toml` in the user's config directory
+    pub fn default_path() -> Option<PathBuf> {
+        let config = std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+            .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config.join(BOOKMARKS_FILE))
+    }
+
+    /// Bookmarks in the file, none if it doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let err = |e: String| format!("{}: {}", path.display(), e);
+        let file = match fs::read_to_string(path) {
+            Ok(text) => toml::from_str::<BookmarksFile>(&text).map_err(|e| err(e.to_string()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BookmarksFile::default(),
+            Err(e) => return Err(err(e.to_string())),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries: file.bookmark.into_iter().map(|bookmark| (bookmark.name, bookmark.location)).collect(),
+        })
+    }
+
+    pub fn entries(&self) -> &[(String, Location)] {
+        &self.entries
+    }
+
+    /// Adds the bookmark, or moves the one with the same name, and writes the file
+    pub fn set(&mut self, name: &str, location: Location) -> Result<(), String> {
+        match self.entries.iter_mut().find(|(entry, _)| entry == name) {
+            Some(entry) => entry.1 = location,
+            None => self.entries.push((name.to_string(), location)),
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let err = |e: String| format!("{}: {}", self.path.display(), e);
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| err(e.to_string()))?;
+        }
+        let file = BookmarksFile {
+            bookmark: self.entries.iter()
+                .map(|(name, location)| Bookmark { name: name.clone(), location: location.clone() })
+                .collect(),
+        };
+        let text = toml::to_string(&file).map_err(|e| err(e.to_string()))?;
+        fs::write(&self.path, text).map_err(|e| err(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bookmarks_keep_their_names() {
+        let path = std::env::temp_dir().join(format!("mandelbrot-bookmarks-{}.toml", std::process::id()));
+        let location = "view = \"-0.75 0.1 0.05\"".parse::<Location>().unwrap();
+
+        let mut bookmarks = Bookmarks::load(&path).unwrap();
+        bookmarks.set("seahorse \"valley\"", location.clone()).unwrap();
+        bookmarks.set("[[bookmark]]", location.clone()).unwrap();
+        let loaded = Bookmarks::load(&path).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(text.matches("[[bookmark]]\n").count(), 2);
+
+        assert_eq!(loaded.entries(), bookmarks.entries());
+        assert_eq!(loaded.entries()[0].0, "seahorse \"valley\"");
+    }
+}
@@ -0,0 +1,226 @@
+use std::{convert::TryFrom, fmt, fs, path::Path, str::FromStr, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use super::{MandelbrotParameters, Coloring, Interior};
+use super::view::{View, Transform};
+use super::formula::Formula;
+use super::newton::Polynomial;
+
+/// Everything needed to get back to a place, saved as a small TOML file.
+/// The view keeps the center's exact decimals, so deep locations load back exactly.
+/// Only the view is required, anything else left out gets the defaults a new view starts with.
+/// ```toml
+/// view = "-0.743 0.13 1e-3"
+/// max_iter = 400
+/// formula = "mandelbrot"
+/// palette = "ultra"
+/// coloring = "smooth"
+/// interior = "flat"
+/// distance_thickness = 0.5
+/// angle = 0.0
+/// skew = 0.0
+/// stretch = 1.0
+/// antialiasing = true
+/// # only for Julia sets and Newton fractals
+/// julia_re = -0.8
+/// julia_im = 0.156
+/// newton = "roots 1+0i -1+0i"
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "LocationFile", into = "LocationFile")]
+pub struct Location {
+    pub view:      View,
+    pub transform: Transform,
+    pub max_iter:  u32,
+    pub formula:   Formula,
+    pub newton:    Option<Arc<Polynomial>>,
+    pub julia_c:   Option<(f64,f64)>,
+    /// Name of the palette, the palettes themselves live in their own files
+    pub palette:   String,
+    pub coloring:  Coloring,
+    pub interior:  Interior,
+    pub distance_thickness: f32,
+    pub antialiasing: bool,
+}
+
+impl Location {
+    pub fn new(params: &MandelbrotParameters, antialiasing: bool) -> Self {
+        Self {
+            view:      params.view.clone(),
+            transform: params.transform,
+            max_iter:  params.max_iter,
+            formula:   params.formula.clone(),
+            newton:    params.newton.clone(),
+            julia_c:   params.julia_c,
+            palette:   params.palette.name.clone(),
+            coloring:  params.coloring,
+            interior:  params.interior,
+            distance_thickness: params.distance_thickness,
+            antialiasing,
+        }
+    }
+
+    /// Sets everything but the palette, which has to be looked up by name
+    pub fn apply(&self, params: &mut MandelbrotParameters) {
+        params.view      = self.view.clone();
+        params.transform = self.transform;
+        params.max_iter  = self.max_iter;
+        params.formula   = self.formula.clone();
+        params.newton    = self.newton.clone();
+        params.julia_c   = self.julia_c;
+        params.coloring  = self.coloring;
+        params.interior  = self.interior;
+        params.distance_thickness = self.distance_thickness;
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        text.parse().map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_string()).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = toml::to_string(self).map_err(|_| fmt::Error)?;
+        write!(f, "{}", text)
+    }
+}
+
+impl FromStr for Location {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s).map_err(|e| e.to_string())
+    }
+}
+
+/// How a location is written down, the view, formula, coloring and polynomial go by their text form
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LocationFile {
+    view:               String,
+    max_iter:           Option<u32>,
+    formula:            Option<String>,
+    palette:            Option<String>,
+    coloring:           Option<String>,
+    interior:           Option<String>,
+    distance_thickness: Option<f32>,
+    angle:              Option<f64>,
+    skew:               Option<f64>,
+    stretch:            Option<f64>,
+    antialiasing:       Option<bool>,
+    julia_re:           Option<f64>,
+    julia_im:           Option<f64>,
+    newton:             Option<String>,
+}
+
+impl From<Location> for LocationFile {
+    fn from(location: Location) -> Self {
+        Self {
+            view:               location.view.to_string(),
+            max_iter:           Some(location.max_iter),
+            formula:            Some(location.formula.to_string()),
+            palette:            Some(location.palette),
+            coloring:           Some(location.coloring.to_string()),
+            interior:           Some(location.interior.to_string()),
+            distance_thickness: Some(location.distance_thickness),
+            angle:              Some(location.transform.angle),
+            skew:               Some(location.transform.skew),
+            stretch:            Some(location.transform.stretch),
+            antialiasing:       Some(location.antialiasing),
+            julia_re:           location.julia_c.map(|(re, _)| re),
+            julia_im:           location.julia_c.map(|(_, im)| im),
+            newton:             location.newton.map(|polynomial| polynomial.to_string()),
+        }
+    }
+}
+
+impl TryFrom<LocationFile> for Location {
+    type Error = String;
+
+    fn try_from(file: LocationFile) -> Result<Self, Self::Error> {
+        let mut location = Self::new(&MandelbrotParameters::new(0, 0), false);
+        location.view = file.view.parse()?;
+        if let Some(max_iter) = file.max_iter {
+            if max_iter == 0 {
+                return Err(format!("invalid max_iter '{}', it has to be at least 1", max_iter));
+            }
+            location.max_iter = max_iter;
+        }
+        if let Some(formula)  = file.formula  { location.formula  = formula.parse()?; }
+        if let Some(palette)  = file.palette  { location.palette  = palette; }
+        if let Some(coloring) = file.coloring { location.coloring = coloring.parse()?; }
+        if let Some(interior) = file.interior { location.interior = interior.parse()?; }
+        if let Some(thickness) = file.distance_thickness { location.distance_thickness = thickness; }
+        if let Some(angle)    = file.angle    { location.transform.angle   = angle; }
+        if let Some(skew)     = file.skew     { location.transform.skew    = skew; }
+        if let Some(stretch)  = file.stretch  { location.transform.stretch = stretch; }
+        if let Some(antialiasing) = file.antialiasing { location.antialiasing = antialiasing; }
+        location.julia_c = match (file.julia_re, file.julia_im) {
+            (Some(re), Some(im)) => Some((re, im)),
+            (None, None) => None,
+            _ => return Err("julia_re and julia_im have to be given together".to_string()),
+        };
+        if let Some(newton) = file.newton {
+            location.newton = Some(Arc::new(newton.parse()?));
+        }
+        Ok(location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(location: &Location) {
+        let text = location.to_string();
+        assert_eq!(&text.parse::<Location>().unwrap(), location, "{}", text);
+    }
+
+    #[test]
+    fn deep_view() {
+        let mut params = MandelbrotParameters::new(100, 100);
+        params.view = "-1.74999841099374081749002483162428393452822172335808534616943930976364725846655540417646727085571962736578151132907961927190726789896685696750162524460775 -0.00000000000000165712469295418692325810961981279189026504290127375760405334498110850956047368308707050735960323397389547038231194872482690340369921750514146922400928554011996123112902000856666847088788158433995358406779259404221904755 1e-150".parse().unwrap();
+        params.max_iter = 20_000;
+        params.coloring = Coloring::Histogram;
+        params.interior = Interior::Period;
+        params.distance_thickness = 0.75;
+        params.transform = Transform { angle: 0.3, skew: -0.1, stretch: 1.5 };
+        round_trip(&Location::new(&params, true));
+    }
+
+    #[test]
+    fn julia_set() {
+        let mut params = MandelbrotParameters::new(100, 100);
+        params.view     = "0 0 1.5".parse().unwrap();
+        params.formula  = "z^3 - 0.5*z + c".parse().unwrap();
+        params.julia_c  = Some((-0.8, 0.156));
+        params.coloring = Coloring::Distance;
+        let mut location = Location::new(&params, false);
+        location.palette = "my \"palette\" \\ 2".to_string();
+        round_trip(&location);
+    }
+
+    #[test]
+    fn newton_fractal() {
+        let mut params = MandelbrotParameters::new(100, 100);
+        params.view   = "0.1 -0.2 2".parse().unwrap();
+        params.newton = Some(Arc::new("roots 1+0i -0.5+0.866i -0.5-0.866i".parse().unwrap()));
+        round_trip(&Location::new(&params, true));
+    }
+
+    #[test]
+    fn defaults() {
+        let location = "# only a view\nview = \"-0.75 0.1 0.05\"".parse::<Location>().unwrap();
+        assert_eq!(location.max_iter, 100);
+        assert_eq!(location.palette, "classic");
+        assert!("view = \"0 0 1\"\nmax_iter = 0".parse::<Location>().is_err());
+        assert!("max_iter = 100".parse::<Location>().is_err());
+        assert!("view = \"0 0 1\"\nzoom = 2".parse::<Location>().is_err());
+    }
+}
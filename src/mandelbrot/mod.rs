@@ -2,33 +2,360 @@ extern crate rayon;
 
 use rayon::prelude::*;
 
+use std::{fmt, str::FromStr, sync::Arc};
+
 #[cfg(feature = "gpu")]
 pub mod gpu;
 #[cfg(feature = "gpu")]
 use gpu::GpuCompute;
 
 pub mod compute;
+use compute::ComputeDoubleDouble;
+pub mod perturbation;
+pub mod view;
+use view::{View, Transform};
+pub mod palette;
+use palette::Palette;
+pub mod formula;
+mod complex;
+pub mod expression;
+pub mod newton;
+pub mod buddhabrot;
+pub mod tiled;
+pub mod location;
+pub mod history;
+use newton::Polynomial;
+use formula::Formula;
+
+// Pixel spacing relative to the view position below which the gpu's f32 gets blocky
+const F64_THRESHOLD: f64 = 1e-6;
+// Pixel spacing relative to the view position below which f64 iteration falls apart
+const DOUBLE_DOUBLE_THRESHOLD: f64 = 1e-13;
+// Same for double-double, which has about twice the digits of f64
+const PERTURBATION_THRESHOLD:  f64 = 1e-28;
+// Iteration limit unless one is given, enough for the whole set to show
+const DEFAULT_MAX_ITER: u32 = 100;
+// Smoothing is only continuous once the bailout is well above 2
+const DEFAULT_BAILOUT: f64 = 256.;
+// Boundary thickness in pixels for distance estimation
+const DEFAULT_DISTANCE_THICKNESS: f32 = 0.5;
+// Distance in boundary thicknesses at which the shading reaches the full palette color
+const DISTANCE_SHADE_RANGE: f32 = 64.;
+// An orbit counts as periodic once it comes back this close, relative to the pixel spacing
+const PERIODICITY_TOLERANCE: f64 = 1e-3;
+
+/// What the palette is indexed with
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum Coloring {
+    /// Integer escape iteration
+    #[default]
+    Iteration,
+    /// Continuous log-log smoothed iteration count
+    Smooth,
+    /// Smoothed iteration count mapped by its cumulative distribution over the frame,
+    /// spreads the palette evenly no matter how high max_iter is
+    Histogram,
+    /// Smooth coloring shaded by the exterior distance estimate,
+    /// everything closer to the boundary than the filament thickness gets the interior color
+    Distance,
+}
+
+impl Coloring {
+    pub fn next(self) -> Self {
+        match self {
+            Coloring::Iteration => Coloring::Smooth,
+            Coloring::Smooth    => Coloring::Histogram,
+            Coloring::Histogram => Coloring::Distance,
+            Coloring::Distance  => Coloring::Iteration,
+        }
+    }
+
+    /// Whether escaped points get a fractional iteration count
+    pub fn is_smooth(self) -> bool {
+        self != Coloring::Iteration
+    }
+}
 
+impl fmt::Display for Coloring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Coloring::Iteration => write!(f, "iteration"),
+            Coloring::Smooth    => write!(f, "smooth"),
+            Coloring::Histogram => write!(f, "histogram"),
+            Coloring::Distance  => write!(f, "distance"),
+        }
+    }
+}
+
+impl FromStr for Coloring {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "iteration" => Ok(Coloring::Iteration),
+            "smooth"    => Ok(Coloring::Smooth),
+            "histogram" => Ok(Coloring::Histogram),
+            "distance"  => Ok(Coloring::Distance),
+            _ => Err(format!("Unknown coloring '{}', expected iteration, smooth, histogram or distance", s)),
+        }
+    }
+}
+
+/// How points which never escaped are colored
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum Interior {
+    /// The palette's interior color
+    #[default]
+    Flat,
+    /// The palette indexed by the period of the orbit
+    Period,
+}
+
+impl Interior {
+    pub fn next(self) -> Self {
+        match self {
+            Interior::Flat   => Interior::Period,
+            Interior::Period => Interior::Flat,
+        }
+    }
+}
+
+impl fmt::Display for Interior {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Interior::Flat   => write!(f, "flat"),
+            Interior::Period => write!(f, "period"),
+        }
+    }
+}
+
+impl FromStr for Interior {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat"   => Ok(Interior::Flat),
+            "period" => Ok(Interior::Period),
+            _ => Err(format!("Unknown interior coloring '{}', expected flat or period", s)),
+        }
+    }
+}
+
+/// Everything iterating a single pixel leaves behind, colored in a separate pass.
+/// Nothing in it depends on the coloring, so switching colorings never iterates again.
+///
+/// Rather than the final z and dz/dc it only keeps what the colorings read from them,
+/// |z|^2 and the distance estimate, so a pixel takes 12 bytes instead of 40.
+/// Colorings needing more of z, like its angle, would have to add it here.
+#[repr(C)]
 #[derive(Debug, Default, Copy, Clone)]
+pub struct Field {
+    pub iter:  u32,
+    /// |z|^2 at the iteration the point escaped at.
+    /// For points which never escaped it's the period of the orbit, 0 if none was detected,
+    /// for Newton fractals the root the point converged to
+    pub value: f32,
+    /// Exterior distance estimate in pixels, `|z|ln|z| / |dz/dc|` with dz/dc times the pixel spacing
+    pub distance: f32,
+}
+
+unsafe impl bytemuck::Zeroable for Field {}
+unsafe impl bytemuck::Pod for Field {}
+
+impl Field {
+    pub fn new(params: &MandelbrotParameters, iter: u32, z: (f64,f64), derivative: (f64,f64)) -> Self {
+        if iter >= params.max_iter {
+            return Self::interior(params.max_iter, 0);
+        }
+        let norm = z.0*z.0 + z.1*z.1;
+        let der  = (derivative.0*derivative.0 + derivative.1*derivative.1).sqrt();
+        Self {
+            iter,
+            value:    norm as f32,
+            distance: (norm.sqrt()*norm.ln() / 2. / der) as f32,
+        }
+    }
+
+    /// Stand in for points which never escaped, `period` is 0 if it isn't known
+    pub fn interior(max_iter: u32, period: u32) -> Self {
+        Self {
+            iter:  max_iter,
+            value: period as f32,
+            ..Default::default()
+        }
+    }
+
+    /// Point of a Newton fractal which converged to the root with index `root`
+    pub fn converged(iter: u32, root: usize) -> Self {
+        Self {
+            iter,
+            value: root as f32,
+            ..Default::default()
+        }
+    }
+
+    /// |z|^2 of a point which escaped
+    pub fn norm(&self) -> f32 {
+        self.value
+    }
+
+    /// Period of the orbit of a point which never escaped, 0 if none was detected
+    pub fn period(&self) -> u32 {
+        self.value as u32
+    }
+
+    /// Root a point of a Newton fractal converged to
+    pub fn root(&self) -> usize {
+        self.value as usize
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct MandelbrotParameters {
-    pub range:    (f64,f64),
-    pub pos:      (f64,f64),
+    pub view:     View,
+    /// Rotation and skew applied around the view's center
+    pub transform: Transform,
     pub max_iter: u32,
+    pub bailout:  f64,
+    pub coloring: Coloring,
+    pub formula:  Formula,
+    pub palette:  Palette,
+    /// Filament thickness in pixels for distance estimation
+    pub distance_thickness: f32,
+    pub interior: Interior,
+    /// Fixed c of the Julia set to render, the Mandelbrot set if none
+    pub julia_c:  Option<(f64,f64)>,
+    /// Renders the Newton fractal of the polynomial instead of `formula`
+    pub newton:   Option<Arc<Polynomial>>,
     width:  u32,
     height: u32,
 }
 
-#[cfg(feature = "gpu")]
 impl MandelbrotParameters {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width, height,
+            max_iter: DEFAULT_MAX_ITER,
+            bailout:  DEFAULT_BAILOUT,
+            distance_thickness: DEFAULT_DISTANCE_THICKNESS,
+            ..Default::default()
+        }
+    }
+
     pub fn set_dimensions(&mut self, width: u32, height: u32) {
         self.width  = width;
         self.height = height;
     }
+
+    /// Half the width and height of the image in the complex plane,
+    /// the view's radius covers the shorter side
+    pub fn extent(&self) -> (f64,f64) {
+        let spacing = self.pixel_spacing();
+        (spacing*self.width as f64 / 2., spacing*self.height as f64 / 2.)
+    }
+
+    pub fn pixel_spacing(&self) -> f64 {
+        2.*self.view.radius() / self.width.min(self.height) as f64
+    }
+
+    /// Offset of the pixel from the view center in the complex plane, transform included
+    pub fn pixel_offset(&self, i: u32) -> (f64,f64) {
+        self.offset_at((i % self.width) as f64, (i / self.width) as f64)
+    }
+
+    /// Same as `pixel_offset` for any point in pixel coordinates, even outside the image
+    pub fn offset_at(&self, x: f64, y: f64) -> (f64,f64) {
+        let spacing = self.pixel_spacing();
+        let extent  = self.extent();
+        self.transform.apply((
+            -extent.0 + x*spacing,
+            -extent.1 + y*spacing,
+        ))
+    }
+
+    /// Parameters for just the `width` by `height` rectangle at (`x`, `y`) of this image,
+    /// at the same pixel spacing and transform
+    pub fn tile(&self, x: u32, y: u32, width: u32, height: u32) -> Self {
+        let spacing = self.pixel_spacing();
+        let extent  = self.extent();
+        // From the image's center to the tile's
+        let offset = self.transform.apply((
+            (x as f64 + width  as f64 / 2.)*spacing - extent.0,
+            (y as f64 + height as f64 / 2.)*spacing - extent.1,
+        ));
+
+        let mut tile = self.clone();
+        // Radius first, so the center gets the digits of the smaller view
+        tile.view.set_radius(spacing*width.min(height) as f64 / 2.);
+        tile.view.translate(offset.0, offset.1);
+        tile.set_dimensions(width, height);
+        tile
+    }
+
+    /// Radius past which a point counts as escaped, the same for every coloring
+    pub fn escape_radius(&self) -> f64 {
+        self.bailout
+    }
+
+    /// Value handed to the palette for a point which stopped at `iter` with `norm` = |z|^2
+    pub fn escape_value(&self, iter: u32, norm: f64) -> f32 {
+        if iter >= self.max_iter {
+            // Never escaped and no period detected
+            return 0.;
+        }
+        if !self.coloring.is_smooth() {
+            return iter as f32;
+        }
+        // n + 1 - log_d(ln|z| / ln(bailout)) with d the degree of the formula
+        let log_z = norm.ln() / 2.;
+        (iter as f64 + 1. - (log_z / self.escape_radius().ln()).ln() / self.formula.degree().ln()) as f32
+    }
+
+    /// Whether the view is too deep for f32, true for every deeper backend as well
+    pub fn needs_f64(&self) -> bool {
+        self.relative_spacing() < F64_THRESHOLD
+    }
+
+    /// Whether the view is too deep for plain f64, true for perturbation depths as well.
+    /// Newton fractals are only rendered in f64.
+    pub fn needs_double_double(&self) -> bool {
+        self.newton.is_none() && self.relative_spacing() < DOUBLE_DOUBLE_THRESHOLD
+    }
+
+    /// Whether the shader can render these parameters, it only has f32
+    #[cfg(feature = "gpu")]
+    pub fn supports_gpu(&self) -> bool {
+        self.newton.is_none() && !self.needs_f64()
+    }
+
+    /// Whether the view is too deep for double-double and has to be rendered with perturbation,
+    /// only the Mandelbrot set is perturbed, everything else stays on double-double
+    pub fn needs_perturbation(&self) -> bool {
+        self.needs_double_double() && self.julia_c.is_none() && self.formula.is_mandelbrot() && self.relative_spacing() < PERTURBATION_THRESHOLD
+    }
+
+    /// Starting z, c and the derivative per pixel at the start for the point at `pos`.
+    /// Julia sets iterate from the point with c fixed, the Mandelbrot set from 0 with c at the point.
+    pub fn start(&self, pos: (f64,f64)) -> ((f64,f64), (f64,f64), (f64,f64)) {
+        match self.julia_c {
+            Some(c) => (pos, c, (self.pixel_spacing(), 0.)),
+            None    => ((0., 0.), pos, (0., 0.)),
+        }
+    }
+
+    fn relative_spacing(&self) -> f64 {
+        let center    = self.view.center_f64();
+        let magnitude = center.0.abs().max(center.1.abs()).max(1.);
+        self.pixel_spacing() / magnitude
+    }
 }
 
 pub struct Mandelbrot {
     params: MandelbrotParameters,
+    fields: Vec::<Field>,
     pixels: Vec::<u8>,
+    skipped_iterations: u32,
+    backend: &'static str,
     pub on_gpu: bool,
     #[cfg(feature = "gpu")]
     gpu_compute: Option<GpuCompute>,
@@ -36,23 +363,16 @@ pub struct Mandelbrot {
 
 impl Mandelbrot {
     pub fn builder(width: u32, height: u32) -> MandelbrotBuilder {
-        #[cfg(feature = "gpu")]
-        let gpu_compute = GpuCompute::new((width*height*3) as usize);
-        #[cfg(feature = "gpu")]
-        if gpu_compute.is_none() {
-            println!("!----- GPU computing is not supported -----!");
-        }
-
         MandelbrotBuilder {
             mandelbrot: Mandelbrot {
-                params: MandelbrotParameters {
-                    width, height,
-                    ..Default::default()
-                },
+                params: MandelbrotParameters::new(width, height),
+                fields: vec![Field::default();(width*height) as usize],
                 pixels: vec![0u8;(width*height*3) as usize], // 3 colors RGB
+                skipped_iterations: 0,
+                backend: "CPU",
                 on_gpu: true,
                 #[cfg(feature = "gpu")]
-                gpu_compute,
+                gpu_compute: None,
             }
         }
     }
@@ -69,76 +389,253 @@ impl Mandelbrot {
         &self.pixels
     }
 
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// Iterations the series approximation let every pixel skip in the last update
+    pub fn skipped_iterations(&self) -> u32 {
+        self.skipped_iterations
+    }
+
+    /// Name of the backend the last update ran on
+    pub fn backend(&self) -> &'static str {
+        self.backend
+    }
+
     pub fn set_pixels(&mut self, pixels: Vec<u8>) {
         self.pixels = pixels;
     }
 
     pub fn set_dimensions(&mut self, width: u32, height: u32) {
+        self.fields.resize((width*height) as usize, Field::default());
         self.pixels.resize((width*height*3) as usize, 0);
         self.params.width  = width;
         self.params.height = height;
     }
 
     pub fn update(&mut self) {
+        self.update_fields();
+        self.recolor();
+    }
+
+    /// Coloring pass, turns the field buffer into pixels without iterating again
+    pub fn recolor(&mut self) {
+        Self::color_fields(&self.params, &self.fields, &mut self.pixels);
+    }
+
+    /// Colors every field, the histogram is built from the same `fields`,
+    /// so supersampled renders get theirs before downsampling
+    pub fn color_fields(params: &MandelbrotParameters, fields: &[Field], pixels: &mut [u8]) {
+        let histogram = match params.coloring {
+            Coloring::Histogram => Some(Self::histogram(params, fields)),
+            _ => None,
+        };
+        Self::color_fields_with(params, fields, histogram.as_deref(), pixels);
+    }
+
+    /// Colors every field, histogram coloring is normalized by `histogram`
+    /// which can be counted over more than these fields, e.g. a whole image rendered in bands
+    pub fn color_fields_with(params: &MandelbrotParameters, fields: &[Field], histogram: Option<&[u64]>, pixels: &mut [u8]) {
+        let cdf = match params.coloring {
+            Coloring::Histogram => histogram.map(Self::cumulative_histogram),
+            _ => None,
+        };
+        pixels.par_chunks_mut(3).zip(fields.par_iter()).for_each(|(pixel, field)| {
+            pixel.copy_from_slice(&Self::color(field, params, cdf.as_deref()));
+        });
+    }
+
+    /// Number of escaped points for every iteration count
+    pub fn histogram(params: &MandelbrotParameters, fields: &[Field]) -> Vec<u64> {
+        let bins = params.max_iter as usize + 1;
+        fields.par_iter()
+            .filter(|field| field.iter < params.max_iter)
+            .fold(|| vec![0u64; bins], |mut counts, field| {
+                counts[field.iter as usize] += 1;
+                counts
+            })
+            .reduce(|| vec![0u64; bins], |mut a, b| {
+                a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                a
+            })
+    }
+
+    /// Share of the escaped points which escaped at or before every iteration count
+    fn cumulative_histogram(counts: &[u64]) -> Vec<f32> {
+        let total = counts.iter().sum::<u64>().max(1) as f32;
+        let mut sum = 0u64;
+        counts.iter().map(|&count| {
+            sum += count;
+            sum as f32 / total
+        }).collect()
+    }
+
+    fn update_fields(&mut self) {
+        self.skipped_iterations = 0;
+        if self.params.needs_perturbation() {
+            self.backend = "Perturbation";
+            self.update_perturbation();
+            return;
+        }
+        if self.params.needs_double_double() {
+            self.backend = "Double-double";
+            ComputeDoubleDouble::render(&self.params, &mut self.fields);
+            return;
+        }
         #[cfg(feature = "gpu")]
-        if self.on_gpu && self.gpu_compute.is_some() {
+        if self.on_gpu && self.params.supports_gpu() && self.gpu_compute.as_ref().is_some_and(|gpu| gpu.fits(self.fields.len())) {
+            self.backend = "GPU";
             self.update_gpu();
             return;
         }
+        self.backend = "CPU";
         self.update_cpu();
     }
 
     #[cfg(feature = "gpu")]
     fn update_gpu(&mut self) {
-        let gpu_out = self.gpu_compute.as_mut().unwrap().compute(&self.params).unwrap();
-        self.pixels = gpu_out.iter().map(|x| *x as u8).collect::<Vec<u8>>();
+        self.fields = self.gpu_compute.as_mut().unwrap().compute(&self.params).unwrap();
     }
     
     fn update_cpu(&mut self) {
-        self.pixels.par_iter_mut().enumerate().step_by(3).for_each(|(i, pixel)| {
-            let iter = Self::compute(&self.params, (i/3) as u32);
-            let color = Self::color(iter, self.params.max_iter);
-
-            unsafe {
-                let pixel = pixel as *mut u8;
-
-                *pixel          = color[0];
-                *(pixel.add(1)) = color[1];
-                *(pixel.add(2)) = color[2];
-            }
+        let center = self.params.view.center_f64();
+        let params = &self.params;
+        self.fields.par_iter_mut().enumerate().for_each(|(i, field)| {
+            *field = Self::compute(params, center, i as u32);
         });
     }
 
-    fn compute(params: &MandelbrotParameters, i: u32) -> u32 {
+    fn compute(params: &MandelbrotParameters, center: (f64,f64), i: u32) -> Field {
+        let extent = params.extent();
         let x: u32 = i % params.width;
-        let y: u32 = i / params.height;
-        let x: f64 = map::<f64>(x as f64, 0., params.width  as f64, params.range.0, params.range.1) + params.pos.0;
-        let y: f64 = map::<f64>(y as f64, 0., params.height as f64, params.range.0, params.range.1) + params.pos.1;
+        let y: u32 = i / params.width;
+        let offset = params.transform.apply((
+            map::<f64>(x as f64, 0., params.width  as f64, -extent.0, extent.0),
+            map::<f64>(y as f64, 0., params.height as f64, -extent.1, extent.1),
+        ));
+        let x: f64 = offset.0 + center.0;
+        let y: f64 = offset.1 + center.1;
+
+        if let Some(polynomial) = &params.newton {
+            return polynomial.iterate(params, (x, y));
+        }
+        if params.julia_c.is_none() && params.formula.is_mandelbrot() {
+            if let Some(period) = Self::known_interior(x, y) {
+                return Field::interior(params.max_iter, period);
+            }
+        }
+
+        let (mut z, c, mut d) = params.start((x, y));
 
-        let mut x2: f64 = 0.;
-        let mut y2: f64 = 0.;
+        let escape  = params.escape_radius()*params.escape_radius();
+        let spacing = params.pixel_spacing();
+        // Derivative per pixel, dz' = f'(z)*dz + spacing, Julia sets don't depend on c
+        let dc = if params.julia_c.is_some() { 0. } else { spacing };
+        let mut periodicity = Periodicity::new(spacing);
 
         let mut iter = 0;
-        while x2*x2+y2*y2 <= 4. && iter < params.max_iter {
-            let x_new = x2*x2-y2*y2 + x;
-            y2 = 2.*x2*y2 +y;
-            x2 = x_new;
+        while z.0*z.0+z.1*z.1 <= escape && iter < params.max_iter {
+            d = params.formula.derivative(z, c, d, dc);
+            z = params.formula.step(z, c);
             iter += 1;
+
+            if let Some(period) = periodicity.check(z) {
+                return Field::interior(params.max_iter, period);
+            }
         }
 
-        iter
+        Field::new(params, iter, z, d)
     }
 
-    fn color(iter: u32, max_iter: u32) -> [u8;3] {
-        //let value = if iter == max_iter {0.} else {1.};
-        //let iter  = (iter as i32 - max_iter as i32).abs() as u32;
-        //let hue   = RgbHue::from_degrees(map::<f32>(iter as f32, 0., max_iter as f32, 0., 359.));
-        let normalized = map(iter as f32, 0., max_iter as f32, 0., 1.);
-        [
-            (9.*(1.-normalized)*normalized*normalized*normalized*255.) as u8,
-            (15.*(1.-normalized)*(1.-normalized)*normalized*normalized*255.) as u8,
-            (8.5*(1.-normalized)*(1.-normalized)*(1.-normalized) * normalized*255.) as u8
-        ]
+    /// Period of the Mandelbrot set's main cardioid or period-2 bulb if c lies in either
+    fn known_interior(x: f64, y: f64) -> Option<u32> {
+        let q = (x-0.25)*(x-0.25) + y*y;
+        if q*(q + (x-0.25)) <= 0.25*y*y {
+            return Some(1);
+        }
+        if (x+1.)*(x+1.) + y*y <= 0.0625 {
+            return Some(2);
+        }
+        None
+    }
+
+    fn color(field: &Field, params: &MandelbrotParameters, cdf: Option<&[f32]>) -> [u8;3] {
+        if let Some(polynomial) = &params.newton {
+            return Self::color_newton(field, params, polynomial);
+        }
+        if field.iter >= params.max_iter {
+            return match (params.interior, field.period()) {
+                (Interior::Period, period) if period > 0 => {
+                    // Low periods are common, the log spreads them over the palette
+                    let max = (params.max_iter.max(2) as f32).ln();
+                    params.palette.sample((period as f32).ln() / max)
+                },
+                _ => params.palette.interior,
+            };
+        }
+        let smooth = params.escape_value(field.iter, field.norm() as f64);
+        let normalized = match cdf {
+            Some(cdf) => {
+                // The fractional part blends towards the next bin
+                let n    = (smooth.max(0.) as usize).min(cdf.len()-1);
+                let next = cdf[(n+1).min(cdf.len()-1)];
+                let f    = (smooth - n as f32).clamp(0., 1.);
+                cdf[n] + (next-cdf[n])*f
+            },
+            None => map(smooth, 0., params.max_iter as f32, 0., 1.),
+        };
+        let color = params.palette.sample(normalized);
+
+        if params.coloring != Coloring::Distance {
+            return color;
+        }
+        // Filaments thinner than a pixel still get drawn at the set thickness
+        let distance = field.distance / params.distance_thickness;
+        if distance.is_nan() || distance < 1. {
+            return params.palette.interior;
+        }
+        // Fades from the interior color at the boundary to the palette further away
+        let shade = (distance.ln() / DISTANCE_SHADE_RANGE.ln()).min(1.);
+        let interior = params.palette.interior;
+        [0, 1, 2].map(|c| (interior[c] as f32 + (color[c] as f32 - interior[c] as f32)*shade) as u8)
+    }
+}
+
+/// Brent's cycle detection, compares z against a saved point
+/// which is moved forward every power of two iterations.
+/// Only used in f64, deeper backends can't tell a cycle from a pixel's neighbours in it
+pub struct Periodicity {
+    saved:     (f64,f64),
+    steps:     u32,
+    limit:     u32,
+    tolerance: f64,
+}
+
+impl Periodicity {
+    pub fn new(spacing: f64) -> Self {
+        let tolerance = spacing*PERIODICITY_TOLERANCE;
+        Self {
+            saved: (0., 0.),
+            steps: 0,
+            limit: 1,
+            tolerance: tolerance*tolerance,
+        }
+    }
+
+    /// Feeds the next z of the orbit, returns the period once it repeats
+    pub fn check(&mut self, z: (f64,f64)) -> Option<u32> {
+        self.steps += 1;
+        let (dx, dy) = (z.0-self.saved.0, z.1-self.saved.1);
+        if dx*dx + dy*dy < self.tolerance {
+            return Some(self.steps);
+        }
+        if self.steps == self.limit {
+            self.saved = z;
+            self.steps = 0;
+            self.limit *= 2;
+        }
+        None
     }
 }
 
@@ -147,8 +644,8 @@ pub struct MandelbrotBuilder {
 }
 
 impl MandelbrotBuilder {
-    pub fn range(mut self, range: (f64, f64)) -> Self {
-        self.mandelbrot.params.range = range;
+    pub fn view(mut self, view: View) -> Self {
+        self.mandelbrot.params.view = view;
         self
     }
 
@@ -157,7 +654,27 @@ impl MandelbrotBuilder {
         self
     }
 
-    pub fn build(self) -> Mandelbrot {
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.mandelbrot.params.palette = palette;
+        self
+    }
+
+    /// Whether to set up the GPU at all, on by default
+    pub fn on_gpu(mut self, on_gpu: bool) -> Self {
+        self.mandelbrot.on_gpu = on_gpu;
+        self
+    }
+
+    #[allow(unused_mut)]
+    pub fn build(mut self) -> Mandelbrot {
+        #[cfg(feature = "gpu")]
+        if self.mandelbrot.on_gpu {
+            let pixel_count = self.mandelbrot.fields.len();
+            self.mandelbrot.gpu_compute = GpuCompute::new(pixel_count);
+            if self.mandelbrot.gpu_compute.is_none() {
+                println!("!----- GPU computing is not supported -----!");
+            }
+        }
         self.mandelbrot
     }
 }
@@ -165,8 +682,11 @@ impl MandelbrotBuilder {
 impl Clone for Mandelbrot {
     fn clone(&self) -> Self {
         Self{
-            params: self.params,
+            params: self.params.clone(),
+            fields: self.fields.clone(),
             pixels: self.pixels.clone(),
+            skipped_iterations: self.skipped_iterations,
+            backend: self.backend,
             on_gpu: false,
             #[cfg(feature = "gpu")]
             gpu_compute: None,
@@ -0,0 +1,215 @@
+use std::{fmt, str::FromStr};
+
+use super::{Mandelbrot, MandelbrotParameters, Field};
+use super::complex::{add, mul, div, norm};
+
+// Newton's method counts as converged once the step is this small, compared squared
+const NEWTON_TOLERANCE: f64 = 1e-20;
+// Durand-Kerner iterations for finding the roots of a polynomial given by coefficients
+const ROOT_ITERATIONS: u32 = 500;
+// Brightness lost per iteration it took to converge
+const NEWTON_SHADE: f32 = 0.05;
+
+/// Polynomial for the Newton fractal, given by either its roots or its coefficients.
+/// Text form is `roots <c> <c> ...` or `coefficients <c> <c> ...` with the highest power first,
+/// complex numbers are written like `-0.5+0.866i`.
+#[derive(Clone, PartialEq)]
+pub struct Polynomial {
+    coefficients: Vec<(f64,f64)>,
+    derivative:   Vec<(f64,f64)>,
+    roots:        Vec<(f64,f64)>,
+    from_roots:   bool,
+}
+
+impl Polynomial {
+    pub fn from_roots(roots: Vec<(f64,f64)>) -> Result<Self, String> {
+        if roots.is_empty() {
+            return Err("A polynomial needs at least one root".to_string());
+        }
+        // Multiplies (z - root) in one root at a time
+        let mut coefficients = vec![(1., 0.)];
+        for root in &roots {
+            let mut next = coefficients.clone();
+            next.push((0., 0.));
+            for (i, coefficient) in coefficients.iter().enumerate() {
+                let product = mul(*coefficient, *root);
+                next[i+1] = (next[i+1].0 - product.0, next[i+1].1 - product.1);
+            }
+            coefficients = next;
+        }
+
+        Ok(Self {
+            derivative: derive(&coefficients),
+            coefficients,
+            roots,
+            from_roots: true,
+        })
+    }
+
+    pub fn from_coefficients(coefficients: Vec<(f64,f64)>) -> Result<Self, String> {
+        let coefficients = coefficients.into_iter()
+            .skip_while(|c| *c == (0., 0.))
+            .collect::<Vec<(f64,f64)>>();
+        if coefficients.len() < 2 {
+            return Err("A polynomial needs a degree of at least 1".to_string());
+        }
+
+        Ok(Self {
+            roots: find_roots(&coefficients),
+            derivative: derive(&coefficients),
+            coefficients,
+            from_roots: false,
+        })
+    }
+
+    pub fn roots(&self) -> &[(f64,f64)] {
+        &self.roots
+    }
+
+    /// Index of the root closest to `z`
+    pub fn nearest_root(&self, z: (f64,f64)) -> usize {
+        self.roots.iter()
+            .map(|root| norm((z.0-root.0, z.1-root.1)))
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(0, |(i, _)| i)
+    }
+
+    /// Runs Newton's method from `z`, the field keeps the point it converged to
+    pub fn iterate(&self, params: &MandelbrotParameters, mut z: (f64,f64)) -> Field {
+        let mut iter = 0;
+        while iter < params.max_iter {
+            let value = eval(&self.coefficients, z);
+            let slope = eval(&self.derivative, z);
+            if slope == (0., 0.) {
+                break;
+            }
+            let step = div(value, slope);
+            z = (z.0-step.0, z.1-step.1);
+            iter += 1;
+
+            if norm(step) < NEWTON_TOLERANCE {
+                return Field::converged(iter, self.nearest_root(z));
+            }
+        }
+        Field::interior(params.max_iter, 0)
+    }
+}
+
+impl fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, values) = match self.from_roots {
+            true  => ("roots", &self.roots),
+            false => ("coefficients", &self.coefficients),
+        };
+        write!(f, "{}", name)?;
+        for (re, im) in values {
+            write!(f, " {}{:+}i", re, im)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Polynomial({})", self)
+    }
+}
+
+impl FromStr for Polynomial {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let kind = parts.next().unwrap_or("");
+        let values = parts
+            .map(|part| parse_complex(part).ok_or_else(|| format!("Invalid complex number '{}'", part)))
+            .collect::<Result<Vec<(f64,f64)>, String>>()?;
+
+        match kind {
+            "roots"        => Self::from_roots(values),
+            "coefficients" => Self::from_coefficients(values),
+            _ => Err(format!("Expected 'roots' or 'coefficients', got '{}'", kind)),
+        }
+    }
+}
+
+impl Mandelbrot {
+    /// Colors by the root the point converged to, darker the longer it took
+    pub(super) fn color_newton(field: &Field, params: &MandelbrotParameters, polynomial: &Polynomial) -> [u8;3] {
+        if field.iter >= params.max_iter {
+            return params.palette.interior;
+        }
+        let color = params.palette.sample((field.root() as f32 + 0.5) / polynomial.roots().len() as f32);
+        let shade = (-(field.iter as f32*NEWTON_SHADE)).exp();
+        color.map(|c| (c as f32 * shade) as u8)
+    }
+}
+
+/// Parses `a`, `bi`, `a+bi` or `a-bi`
+fn parse_complex(s: &str) -> Option<(f64,f64)> {
+    let s = s.trim();
+    let imaginary = match s.strip_suffix('i') {
+        Some(rest) => rest,
+        None => return s.parse().ok().map(|re| (re, 0.)),
+    };
+    // The sign between the parts, skipping a leading sign and exponents
+    let split = imaginary.char_indices()
+        .filter(|&(i, ch)| i > 0 && (ch == '+' || ch == '-') && !imaginary[..i].ends_with(['e', 'E']))
+        .map(|(i, _)| i)
+        .next_back();
+    let (re, im) = match split {
+        Some(i) => (imaginary[..i].parse().ok()?, &imaginary[i..]),
+        None    => (0., imaginary),
+    };
+    let im = match im {
+        "" | "+" => 1.,
+        "-"      => -1.,
+        _        => im.parse().ok()?,
+    };
+    Some((re, im))
+}
+
+// Durand-Kerner, improves guesses for every root at once
+fn find_roots(coefficients: &[(f64,f64)]) -> Vec<(f64,f64)> {
+    let lead   = coefficients[0];
+    let monic  = coefficients.iter().map(|c| div(*c, lead)).collect::<Vec<(f64,f64)>>();
+    let degree = coefficients.len()-1;
+
+    // Powers of a number which is neither real nor a root of unity
+    let mut roots = Vec::with_capacity(degree);
+    let mut guess = (1., 0.);
+    for _ in 0..degree {
+        roots.push(guess);
+        guess = mul(guess, (0.4, 0.9));
+    }
+
+    for _ in 0..ROOT_ITERATIONS {
+        for i in 0..degree {
+            let mut denominator = (1., 0.);
+            for j in 0..degree {
+                if i != j {
+                    denominator = mul(denominator, (roots[i].0-roots[j].0, roots[i].1-roots[j].1));
+                }
+            }
+            let step = div(eval(&monic, roots[i]), denominator);
+            roots[i] = (roots[i].0-step.0, roots[i].1-step.1);
+        }
+    }
+    roots
+}
+
+fn derive(coefficients: &[(f64,f64)]) -> Vec<(f64,f64)> {
+    let degree = coefficients.len()-1;
+    coefficients[..degree].iter().enumerate()
+        .map(|(i, c)| {
+            let power = (degree-i) as f64;
+            (c.0*power, c.1*power)
+        })
+        .collect()
+}
+
+// Horner's scheme, highest power first
+fn eval(coefficients: &[(f64,f64)], z: (f64,f64)) -> (f64,f64) {
+    coefficients.iter().fold((0., 0.), |acc, c| add(mul(acc, z), *c))
+}
@@ -0,0 +1,343 @@
+use std::{fmt, fs, path::Path, str::FromStr};
+
+// Gradients are baked into a table so the coloring pass never interpolates
+const LUT_SIZE: usize = 4096;
+const PALETTE_EXTENSION: &str = "palette";
+
+/// Color space the stops are blended in
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Interpolation {
+    Rgb,
+    Hsv,
+    Oklab,
+}
+
+/// What happens past the ends of the gradient
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mode {
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+/// Color gradient the coloring pass looks values up in.
+///
+/// Text form, one setting per line and comment lines starting with `#`:
+/// ```text
+/// # rgb, hsv or oklab
+/// interpolation oklab
+/// # clamp, repeat or mirror
+/// mode mirror
+/// offset 0.1
+/// scale 4
+/// interior #000000
+/// stop 0.0 #000764
+/// stop 1.0 #edffff
+/// ```
+#[derive(Clone)]
+pub struct Palette {
+    pub name:     String,
+    pub mode:     Mode,
+    pub offset:   f32,
+    pub scale:    f32,
+    /// Color of points which never escaped
+    pub interior: [u8;3],
+    stops: Vec<(f32, [u8;3])>,
+    interpolation: Interpolation,
+    lut: Vec<[u8;3]>,
+}
+
+impl Palette {
+    pub fn new(name: &str, mut stops: Vec<(f32, [u8;3])>, interpolation: Interpolation) -> Result<Self, String> {
+        if stops.is_empty() {
+            return Err(format!("Palette '{}' has no stops", name));
+        }
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut palette = Self {
+            name: name.to_string(),
+            mode: Mode::Clamp,
+            offset: 0.,
+            scale:  1.,
+            interior: [0, 0, 0],
+            stops,
+            interpolation,
+            lut: Vec::new(),
+        };
+        palette.lut = (0..LUT_SIZE)
+            .map(|i| palette.interpolate(i as f32 / (LUT_SIZE-1) as f32))
+            .collect();
+        Ok(palette)
+    }
+
+    /// The polynomial the explorer has always been colored with
+    pub fn classic() -> Self {
+        let stops = (0..=64).map(|i| {
+            let t = i as f32 / 64.;
+            (t, [
+                (9.*(1.-t)*t*t*t*255.) as u8,
+                (15.*(1.-t)*(1.-t)*t*t*255.) as u8,
+                (8.5*(1.-t)*(1.-t)*(1.-t)*t*255.) as u8,
+            ])
+        }).collect();
+        Self::new("classic", stops, Interpolation::Rgb).unwrap()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut palette = text.parse::<Self>().map_err(|e| format!("{}: {}", path.display(), e))?;
+        if let Some(name) = path.file_stem() {
+            palette.name = name.to_string_lossy().into_owned();
+        }
+        Ok(palette)
+    }
+
+    /// Every palette file in the directory sorted by name, the ones which fail to load are reported and skipped
+    pub fn load_dir(path: &Path) -> Vec<Self> {
+        let mut paths = match fs::read_dir(path) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == PALETTE_EXTENSION))
+                .collect::<Vec<_>>(),
+            Err(e) => {
+                println!("!----- Could not read palettes from {}: {} -----!", path.display(), e);
+                Vec::new()
+            },
+        };
+        paths.sort();
+
+        paths.iter().filter_map(|path| match Self::load(path) {
+            Ok(palette) => Some(palette),
+            Err(e) => {
+                println!("!----- {} -----!", e);
+                None
+            },
+        }).collect()
+    }
+
+    /// Color at `t`, 0 being the first stop and 1 the last one before offset and scale
+    pub fn sample(&self, t: f32) -> [u8;3] {
+        let t = t*self.scale + self.offset;
+        let t = match self.mode {
+            Mode::Clamp  => t,
+            Mode::Repeat => t.rem_euclid(1.),
+            Mode::Mirror => 1. - (t.rem_euclid(2.) - 1.).abs(),
+        };
+        let t = if t.is_nan() { 0. } else { t.clamp(0., 1.) };
+        self.lut[(t * (LUT_SIZE-1) as f32).round() as usize]
+    }
+
+    fn interpolate(&self, t: f32) -> [u8;3] {
+        let next = self.stops.iter().position(|stop| stop.0 >= t);
+        let (a, b) = match next {
+            Some(0)    => return self.stops[0].1,
+            None       => return self.stops[self.stops.len()-1].1,
+            Some(next) => (self.stops[next-1], self.stops[next]),
+        };
+        let f = if b.0 > a.0 { (t-a.0) / (b.0-a.0) } else { 1. };
+
+        match self.interpolation {
+            Interpolation::Rgb   => from_f32(lerp(to_f32(a.1), to_f32(b.1), f)),
+            Interpolation::Hsv   => {
+                let (a, mut b) = (rgb_to_hsv(a.1), rgb_to_hsv(b.1));
+                // Go around the shorter way
+                if b[0]-a[0] > 0.5 {
+                    b[0] -= 1.;
+                } else if a[0]-b[0] > 0.5 {
+                    b[0] += 1.;
+                }
+                let mut hsv = lerp(a, b, f);
+                hsv[0] = hsv[0].rem_euclid(1.);
+                hsv_to_rgb(hsv)
+            },
+            Interpolation::Oklab => oklab_to_rgb(lerp(rgb_to_oklab(a.1), rgb_to_oklab(b.1), f)),
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::classic()
+    }
+}
+
+impl fmt::Debug for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Palette({})", self.name)
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut stops         = Vec::new();
+        let mut interpolation = Interpolation::Rgb;
+        let mut mode          = Mode::Clamp;
+        let mut offset        = 0.;
+        let mut scale         = 1.;
+        let mut interior      = [0, 0, 0];
+
+        for (n, line) in s.lines().enumerate() {
+            let line  = line.trim();
+            if line.starts_with('#') {
+                continue;
+            }
+            let parts = line.split_whitespace().collect::<Vec<&str>>();
+            let err   = |what: &str| format!("line {}: {}", n+1, what);
+            match parts.as_slice() {
+                [] => {},
+                ["interpolation", "rgb"]   => interpolation = Interpolation::Rgb,
+                ["interpolation", "hsv"]   => interpolation = Interpolation::Hsv,
+                ["interpolation", "oklab"] => interpolation = Interpolation::Oklab,
+                ["mode", "clamp"]  => mode = Mode::Clamp,
+                ["mode", "repeat"] => mode = Mode::Repeat,
+                ["mode", "mirror"] => mode = Mode::Mirror,
+                ["offset", val] => offset = val.parse().map_err(|_| err("invalid offset"))?,
+                ["scale", val]  => scale  = val.parse().map_err(|_| err("invalid scale"))?,
+                ["interior", color] => interior = parse_color(color).ok_or_else(|| err("invalid color"))?,
+                ["stop", pos, color] => stops.push((
+                    pos.parse().map_err(|_| err("invalid stop position"))?,
+                    parse_color(color).ok_or_else(|| err("invalid color"))?,
+                )),
+                _ => return Err(err(&format!("unknown setting '{}'", line))),
+            }
+        }
+
+        let mut palette = Self::new("unnamed", stops, interpolation)?;
+        palette.mode     = mode;
+        palette.offset   = offset;
+        palette.scale    = scale;
+        palette.interior = interior;
+        Ok(palette)
+    }
+}
+
+fn parse_color(s: &str) -> Option<[u8;3]> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    // Sliced by bytes below
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i+2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+fn lerp(a: [f32;3], b: [f32;3], f: f32) -> [f32;3] {
+    [a[0] + (b[0]-a[0])*f, a[1] + (b[1]-a[1])*f, a[2] + (b[2]-a[2])*f]
+}
+
+fn to_f32(c: [u8;3]) -> [f32;3] {
+    [c[0] as f32 / 255., c[1] as f32 / 255., c[2] as f32 / 255.]
+}
+
+fn from_f32(c: [f32;3]) -> [u8;3] {
+    let channel = |v: f32| (v.clamp(0., 1.)*255.).round() as u8;
+    [channel(c[0]), channel(c[1]), channel(c[2])]
+}
+
+fn rgb_to_hsv(c: [u8;3]) -> [f32;3] {
+    let [r, g, b] = to_f32(c);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max-min;
+
+    let hue = if delta == 0. {
+        0.
+    } else if max == r {
+        ((g-b) / delta).rem_euclid(6.)
+    } else if max == g {
+        (b-r) / delta + 2.
+    } else {
+        (r-g) / delta + 4.
+    };
+    let saturation = if max == 0. { 0. } else { delta/max };
+
+    [hue/6., saturation, max]
+}
+
+fn hsv_to_rgb(c: [f32;3]) -> [u8;3] {
+    let [h, s, v] = c;
+    let h = h*6.;
+    let chroma = v*s;
+    let x = chroma * (1. - (h.rem_euclid(2.) - 1.).abs());
+    let m = v-chroma;
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.),
+        1 => (x, chroma, 0.),
+        2 => (0., chroma, x),
+        3 => (0., x, chroma),
+        4 => (x, 0., chroma),
+        _ => (chroma, 0., x),
+    };
+    from_f32([r+m, g+m, b+m])
+}
+
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 { v/12.92 } else { ((v+0.055)/1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 { v*12.92 } else { 1.055*v.powf(1./2.4) - 0.055 }
+}
+
+// https://bottosson.github.io/posts/oklab/, constants kept as published
+#[allow(clippy::excessive_precision)]
+fn rgb_to_oklab(c: [u8;3]) -> [f32;3] {
+    let [r, g, b] = to_f32(c).map(srgb_to_linear);
+    let l = (0.4122214708*r + 0.5363325363*g + 0.0514459929*b).cbrt();
+    let m = (0.2119034982*r + 0.6806995451*g + 0.1073969566*b).cbrt();
+    let s = (0.0883024619*r + 0.2817188376*g + 0.6299787005*b).cbrt();
+    [
+        0.2104542553*l + 0.7936177850*m - 0.0040720468*s,
+        1.9779984951*l - 2.4285922050*m + 0.4505937099*s,
+        0.0259040371*l + 0.7827717662*m - 0.8086757660*s,
+    ]
+}
+
+#[allow(clippy::excessive_precision)]
+fn oklab_to_rgb(c: [f32;3]) -> [u8;3] {
+    let [lightness, a, b] = c;
+    let l = (lightness + 0.3963377774*a + 0.2158037573*b).powi(3);
+    let m = (lightness - 0.1055613458*a - 0.0638541728*b).powi(3);
+    let s = (lightness - 0.0894841775*a - 1.2914855480*b).powi(3);
+    from_f32([
+         4.0767416621*l - 3.3077115913*m + 0.2309699292*s,
+        -1.2684380046*l + 2.6097574011*m - 0.3413193965*s,
+        -0.0041960863*l - 0.7034186147*m + 1.7076147010*s,
+    ].map(linear_to_srgb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("#00ff7F"), Some([0, 255, 127]));
+        assert_eq!(parse_color("102030"),  Some([16, 32, 48]));
+        assert_eq!(parse_color("#fff"),    None);
+        assert_eq!(parse_color("#1020304"), None);
+        assert_eq!(parse_color("#10203g"), None);
+        assert_eq!(parse_color("#aébbb"),  None);
+    }
+
+    #[test]
+    fn palette_file() {
+        let palette = "# comment\ninterpolation oklab\nmode mirror\nscale 4\ninterior #102030\nstop 1.0 #ffffff\nstop 0.0 #000000"
+            .parse::<Palette>().unwrap();
+        assert_eq!(palette.interpolation, Interpolation::Oklab);
+        assert_eq!(palette.mode, Mode::Mirror);
+        assert_eq!(palette.scale, 4.);
+        assert_eq!(palette.interior, [16, 32, 48]);
+        // Sorted by position
+        assert_eq!(palette.stops, vec![(0., [0, 0, 0]), (1., [255, 255, 255])]);
+    }
+
+    #[test]
+    fn invalid_colors() {
+        assert_eq!("stop 0 #aébbb".parse::<Palette>().unwrap_err(), "line 1: invalid color");
+        assert_eq!("stop 0 #abc".parse::<Palette>().unwrap_err(),   "line 1: invalid color");
+        assert_eq!("interior #12345".parse::<Palette>().unwrap_err(), "line 1: invalid color");
+    }
+}
@@ -0,0 +1,268 @@
+extern crate dashu_float;
+
+use rayon::prelude::*;
+use dashu_float::{FBig, round::mode::HalfAway};
+
+use super::{Mandelbrot, MandelbrotParameters, Field};
+use super::complex::{add, sub, mul, norm};
+
+pub type BigFloat = FBig<HalfAway, 2>;
+
+// Pauldelbrot's criterion: |Z+d| < tolerance*|Z|, compared squared
+const GLITCH_TOLERANCE: f64 = 1e-6;
+// How many secondary references are tried before the leftover glitches are given up on
+const MAX_REFERENCES: usize = 64;
+// Largest relative error of the series approximation at any probe point
+const SERIES_TOLERANCE: f64 = 1e-10;
+
+pub fn big_float(val: f64, precision: usize) -> BigFloat {
+    BigFloat::try_from(val).unwrap_or(BigFloat::ZERO).with_precision(precision).value()
+}
+
+/// Orbit of a single point computed in high precision and rounded to f64,
+/// every other pixel is iterated as a small f64 delta relative to it
+pub struct ReferenceOrbit {
+    orbit:  Vec<(f64,f64)>,
+    escape: f64,
+}
+
+impl ReferenceOrbit {
+    pub fn new(c: &(BigFloat, BigFloat), max_iter: u32, escape_radius: f64) -> Self {
+        let escape    = escape_radius*escape_radius;
+        let precision = c.0.precision().max(c.1.precision());
+        let mut x = big_float(0., precision);
+        let mut y = big_float(0., precision);
+
+        let mut orbit = Vec::with_capacity(max_iter as usize + 1);
+        orbit.push((0., 0.));
+        for _ in 0..max_iter {
+            let xy = &x * &y;
+            let x_new = &x * &x - &y * &y + &c.0;
+            y = &xy + &xy + &c.1;
+            x = x_new;
+
+            let z = (x.to_f64().value(), y.to_f64().value());
+            orbit.push(z);
+            // The escaping point is kept so pixels may escape together with the reference
+            if z.0*z.0+z.1*z.1 > escape {
+                break;
+            }
+        }
+
+        Self {
+            orbit,
+            escape,
+        }
+    }
+
+    /// Iterates the pixel at `dc` away from the reference.
+    /// Returns how deep the glitch was if the pixel can't be trusted.
+    pub fn iterate(&self, params: &MandelbrotParameters, dc: (f64,f64)) -> Result<Field, f64> {
+        self.iterate_from(params, 0, (0., 0.), (0., 0.), dc)
+    }
+
+    /// Same as `iterate` but starts at iteration `start` with the delta at `d`
+    /// and the derivative per pixel at `der`
+    pub fn iterate_from(&self, params: &MandelbrotParameters, start: u32, d: (f64,f64), der: (f64,f64), dc: (f64,f64)) -> Result<Field, f64> {
+        let (mut dx, mut dy) = d;
+        let (mut ddx, mut ddy) = der;
+        let (mut x, mut y) = self.orbit[start as usize];
+        x += dx;
+        y += dy;
+
+        let spacing = params.pixel_spacing();
+
+        let mut iter = start;
+        while iter < params.max_iter {
+            // dz' = 2*z*dz + spacing, on the full z
+            let ddx_new = 2.*(x*ddx - y*ddy) + spacing;
+            ddy = 2.*(x*ddy + y*ddx);
+            ddx = ddx_new;
+
+            let (zx, zy) = self.orbit[iter as usize];
+            // d' = 2Zd + d^2 + dc
+            let dx_new = 2.*(zx*dx - zy*dy) + dx*dx - dy*dy + dc.0;
+            dy = 2.*(zx*dy + zy*dx) + 2.*dx*dy + dc.1;
+            dx = dx_new;
+            iter += 1;
+
+            // The reference escaped before this pixel did
+            let (zx, zy) = match self.orbit.get(iter as usize) {
+                Some(z) => *z,
+                None    => return Err(1.),
+            };
+
+            x = zx+dx;
+            y = zy+dy;
+            let ref_mag = zx*zx+zy*zy;
+            let mag     = x*x+y*y;
+            if mag > self.escape {
+                break;
+            }
+            if mag < GLITCH_TOLERANCE*ref_mag {
+                return Err(mag/ref_mag);
+            }
+        }
+
+        Ok(Field::new(params, iter, (x, y), (ddx, ddy)))
+    }
+}
+
+/// Truncated Taylor series of the delta in dc, `d_n ~ A_n*dc + B_n*dc^2 + C_n*dc^3`.
+/// Lets every pixel around a reference start iterating at `skip` instead of 0.
+pub struct SeriesApproximation {
+    skip: u32,
+    coefficients: [(f64,f64);3],
+}
+
+impl SeriesApproximation {
+    /// Advances the coefficients along the reference for as long as they
+    /// agree with the exactly iterated probe points
+    pub fn new(reference: &ReferenceOrbit, probes: &[(f64,f64)], max_iter: u32) -> Self {
+        let mut series = Self {
+            skip: 0,
+            coefficients: [(0., 0.);3],
+        };
+        let mut deltas = vec![(0., 0.); probes.len()];
+
+        let limit = (max_iter as usize).min(reference.orbit.len()-1);
+        for n in 0..limit {
+            let z = reference.orbit[n];
+            let z2 = mul((2., 0.), z);
+            let [a, b, c] = series.coefficients;
+            let next = Self {
+                skip: n as u32 + 1,
+                coefficients: [
+                    add(mul(z2, a), (1., 0.)),
+                    add(mul(z2, b), mul(a, a)),
+                    add(mul(z2, c), mul((2., 0.), mul(a, b))),
+                ],
+            };
+
+            let z_next = reference.orbit[n+1];
+            let mut valid = true;
+            for (d, dc) in deltas.iter_mut().zip(probes) {
+                *d = add(add(mul(z2, *d), mul(*d, *d)), *dc);
+
+                let full = add(z_next, *d);
+                let err  = sub(next.evaluate(*dc), *d);
+                if norm(full) > 4. || norm(err) > SERIES_TOLERANCE*SERIES_TOLERANCE*norm(*d) {
+                    valid = false;
+                }
+            }
+            if !valid {
+                break;
+            }
+            series = next;
+        }
+
+        series
+    }
+
+    /// Iterations every pixel can skip
+    pub fn skip(&self) -> u32 {
+        self.skip
+    }
+
+    /// Delta at iteration `skip` for the pixel at `dc` away from the reference
+    pub fn evaluate(&self, dc: (f64,f64)) -> (f64,f64) {
+        let [a, b, c] = self.coefficients;
+        // Horner's scheme, ((C*dc + B)*dc + A)*dc
+        mul(add(mul(add(mul(c, dc), b), dc), a), dc)
+    }
+
+    /// Derivative of the delta in dc at iteration `skip`, `A + 2B*dc + 3C*dc^2`
+    pub fn derivative(&self, dc: (f64,f64)) -> (f64,f64) {
+        let [a, b, c] = self.coefficients;
+        add(mul(add(mul((3.*c.0, 3.*c.1), dc), (2.*b.0, 2.*b.1)), dc), a)
+    }
+}
+
+impl Mandelbrot {
+    pub(super) fn update_perturbation(&mut self) {
+        let params    = &self.params;
+        let precision = params.view.binary_precision();
+        let center    = params.view.center_binary(precision);
+        let spacing   = params.pixel_spacing();
+
+        let mut results: Vec<Result<Field, f64>> = vec![Err(1.); (params.width*params.height) as usize];
+        let mut reference        = ReferenceOrbit::new(&center, params.max_iter, params.escape_radius());
+        let mut reference_offset = (0., 0.);
+
+        // Corners and edge midpoints bound every pixel in the view
+        let (w, h) = (params.width, params.height);
+        let probes = [(0, 0), (w/2, 0), (w-1, 0), (0, h/2), (w-1, h/2), (0, h-1), (w/2, h-1), (w-1, h-1)]
+            .iter()
+            .map(|(x, y)| params.pixel_offset(y*w + x))
+            .collect::<Vec<(f64,f64)>>();
+        // Only the primary reference is approximated, secondary ones only cover small glitches
+        let mut series = Some(SeriesApproximation::new(&reference, &probes, params.max_iter));
+        self.skipped_iterations = series.as_ref().map_or(0, |series| series.skip());
+
+        for _ in 0..MAX_REFERENCES {
+            results.par_iter_mut().enumerate().filter(|(_, res)| res.is_err()).for_each(|(i, res)| {
+                let offset = params.pixel_offset(i as u32);
+                let dc = (offset.0 - reference_offset.0, offset.1 - reference_offset.1);
+                *res = match &series {
+                    Some(series) => {
+                        let der = series.derivative(dc);
+                        let der = (der.0*spacing, der.1*spacing);
+                        reference.iterate_from(params, series.skip(), series.evaluate(dc), der, dc)
+                    },
+                    None => reference.iterate(params, dc),
+                };
+            });
+            series = None;
+
+            // Rebase onto the deepest glitched pixel, it lies closest to what went wrong
+            let glitched = results.iter().enumerate()
+                .filter_map(|(i, res)| res.err().map(|depth| (i, depth)))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            let i = match glitched {
+                Some((i, _)) => i,
+                None => break,
+            };
+
+            reference_offset = params.pixel_offset(i as u32);
+            let c = (
+                &center.0 + big_float(reference_offset.0, precision),
+                &center.1 + big_float(reference_offset.1, precision),
+            );
+            reference = ReferenceOrbit::new(&c, params.max_iter, params.escape_radius());
+        }
+
+        let interior = Field::interior(params.max_iter, 0);
+        self.fields.par_iter_mut().zip(results.par_iter()).for_each(|(field, res)| {
+            *field = res.unwrap_or(interior);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::compute::ComputeDoubleDouble;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a-b).abs() <= 1e-4*a.abs().max(b.abs()), "{} != {}", a, b);
+    }
+
+    #[test]
+    fn matches_double_double() {
+        // Double-double keeps only a few digits below the pixel spacing at 1e-27,
+        // so the view is next to the tip of the antenna where nothing is finer than a pixel
+        let mut mandelbrot = Mandelbrot::builder(16, 16).build();
+        mandelbrot.params.view = "-1.99999999999999999999999999 0.000000000000000000000000002 1e-27".parse().unwrap();
+        mandelbrot.params.max_iter = 1000;
+        mandelbrot.update_perturbation();
+
+        let mut fields = vec![Field::default(); 16*16];
+        ComputeDoubleDouble::render(&mandelbrot.params, &mut fields);
+
+        for (perturbed, double_double) in mandelbrot.fields.iter().zip(&fields) {
+            assert_eq!(perturbed.iter, double_double.iter);
+            assert_close(perturbed.value, double_double.value);
+            assert_close(perturbed.distance, double_double.distance);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+extern crate png;
+extern crate tiff;
+
+use rayon::prelude::*;
+
+use super::{Mandelbrot, MandelbrotParameters, Field, Coloring};
+
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::Path,
+};
+
+// A tile has to fit in the gpu's field buffer, which is limited to 128 MiB
+const MAX_TILE_PIXELS: usize = (128 << 20) / std::mem::size_of::<Field>();
+
+/// Renders images of any size as bands of full width rows, one after another.
+/// Only a single band is in memory at a time, so the rows can be streamed straight into a file.
+/// Histogram coloring needs the whole image's histogram first, so every band is iterated twice.
+pub struct TiledRender {
+    params:      MandelbrotParameters,
+    supersample: u32,
+    on_gpu:      bool,
+}
+
+impl TiledRender {
+    /// `params` at the size of the output, every pixel averages `supersample` squared samples
+    pub fn new(params: MandelbrotParameters, supersample: u32, on_gpu: bool) -> Self {
+        let supersample = supersample.max(1);
+        let mut params = params;
+        params.set_dimensions(params.width*supersample, params.height*supersample);
+        // Filaments keep their thickness in output pixels
+        params.distance_thickness *= supersample as f32;
+
+        Self {
+            params,
+            supersample,
+            on_gpu,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.params.width / self.supersample
+    }
+
+    pub fn height(&self) -> u32 {
+        self.params.height / self.supersample
+    }
+
+    /// Output rows computed at once
+    pub fn rows_per_tile(&self) -> u32 {
+        let samples_per_row = self.params.width as usize * self.supersample as usize;
+        ((MAX_TILE_PIXELS / samples_per_row) as u32).clamp(1, self.height())
+    }
+
+    /// Number of bands iterated in total, `progress` counts up to it
+    pub fn steps(&self) -> u32 {
+        let tiles = self.height().div_ceil(self.rows_per_tile());
+        match self.params.coloring {
+            Coloring::Histogram => tiles*2,
+            _ => tiles,
+        }
+    }
+
+    /// Calls `write_rows` with the RGB rows of every band, top to bottom,
+    /// and `progress` with the number of bands done out of `steps` after each of them
+    pub fn render(
+        &self,
+        mut progress: impl FnMut(u32, u32),
+        mut write_rows: impl FnMut(&[u8]) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let rows   = self.rows_per_tile();
+        let tiles  = self.height().div_ceil(rows);
+        let steps  = self.steps();
+        let (tile_width, tile_height) = (self.params.width, rows*self.supersample);
+        // Every tile has the same size so the gpu buffer fits all of them, the last one is cut off
+        let mut mandelbrot = Mandelbrot::builder(tile_width, tile_height)
+            .on_gpu(self.on_gpu)
+            .build();
+        // Rows of the last tile below the image don't count
+        let samples = |tile: u32| tile_width as usize * (self.params.height - tile*tile_height).min(tile_height) as usize;
+
+        // Only the bin counts are kept from the first pass
+        let histogram = match self.params.coloring {
+            Coloring::Histogram => {
+                let mut histogram = vec![0u64; self.params.max_iter as usize + 1];
+                for tile in 0..tiles {
+                    *mandelbrot.params_mut() = self.params.tile(0, tile*tile_height, tile_width, tile_height);
+                    mandelbrot.update_fields();
+                    let counts = Mandelbrot::histogram(&self.params, &mandelbrot.fields()[..samples(tile)]);
+                    histogram.iter_mut().zip(counts).for_each(|(a, b)| *a += b);
+                    progress(tile+1, steps);
+                }
+                Some(histogram)
+            },
+            _ => None,
+        };
+
+        let mut pixels = vec![0u8; tile_width as usize*tile_height as usize*3];
+        for tile in 0..tiles {
+            let params = self.params.tile(0, tile*tile_height, tile_width, tile_height);
+            *mandelbrot.params_mut() = params.clone();
+            mandelbrot.update_fields();
+            Mandelbrot::color_fields_with(&params, mandelbrot.fields(), histogram.as_deref(), &mut pixels);
+            progress(steps - tiles + tile+1, steps);
+
+            let band   = self.downsample(&pixels, rows);
+            let height = rows.min(self.height() - tile*rows);
+            write_rows(&band[..self.width() as usize*height as usize*3])?;
+        }
+        Ok(())
+    }
+
+    /// Renders into a file, PNG and TIFF are streamed while anything else is kept in memory.
+    /// `progress` is passed on to `render`
+    pub fn save(&self, path: &Path, progress: impl FnMut(u32, u32)) -> Result<(), String> {
+        let err = |e: String| format!("{}: {}", path.display(), e);
+        let extension = path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+
+        match extension.as_deref() {
+            Some("png") => {
+                let file = File::create(path).map_err(|e| err(e.to_string()))?;
+                let mut encoder = png::Encoder::new(BufWriter::new(file), self.width(), self.height());
+                encoder.set_color(png::ColorType::RGB);
+                encoder.set_depth(png::BitDepth::Eight);
+                let mut writer = encoder.write_header().map_err(|e| err(e.to_string()))?;
+                let mut stream = writer.stream_writer();
+                self.render(progress, |rows| std::io::Write::write_all(&mut stream, rows).map_err(|e| err(e.to_string())))?;
+                stream.finish().map_err(|e| err(e.to_string()))
+            },
+            Some("tif") | Some("tiff") => {
+                let file = File::create(path).map_err(|e| err(e.to_string()))?;
+                let mut encoder = tiff::encoder::TiffEncoder::new(BufWriter::new(file)).map_err(|e| err(e.to_string()))?;
+                let mut image = encoder.new_image::<tiff::encoder::colortype::RGB8>(self.width(), self.height())
+                    .map_err(|e| err(e.to_string()))?;
+                // A strip for every band
+                image.rows_per_strip(self.rows_per_tile()).map_err(|e| err(e.to_string()))?;
+                self.render(progress, |rows| image.write_strip(rows).map_err(|e| err(e.to_string())))?;
+                image.finish().map_err(|e| err(e.to_string()))
+            },
+            _ => {
+                let size = (self.width() as usize).checked_mul(self.height() as usize*3)
+                    .filter(|&size| size <= isize::MAX as usize)
+                    .ok_or_else(|| err("Too large to keep in memory, save it as PNG or TIFF".to_string()))?;
+                let mut pixels = Vec::with_capacity(size);
+                self.render(progress, |rows| {
+                    pixels.extend_from_slice(rows);
+                    Ok(())
+                })?;
+                image::save_buffer(path, &pixels, self.width(), self.height(), image::ColorType::Rgb8)
+                    .map_err(|e| err(e.to_string()))
+            },
+        }
+    }
+
+    /// Averages every square of samples into one pixel
+    fn downsample(&self, pixels: &[u8], rows: u32) -> Vec<u8> {
+        let n = self.supersample as usize;
+        if n == 1 {
+            return pixels.to_vec();
+        }
+
+        let width  = self.width() as usize;
+        let stride = self.params.width as usize*3;
+        let mut band = vec![0u8; width*rows as usize*3];
+        band.par_chunks_mut(width*3).enumerate().for_each(|(y, row)| {
+            for x in 0..width {
+                let mut sum = [0u32;3];
+                for sy in 0..n {
+                    for sx in 0..n {
+                        let i = (y*n + sy)*stride + (x*n + sx)*3;
+                        for (sum, value) in sum.iter_mut().zip(&pixels[i..i+3]) {
+                            *sum += *value as u32;
+                        }
+                    }
+                }
+                for (c, sum) in sum.iter().enumerate() {
+                    row[x*3 + c] = (sum / (n*n) as u32) as u8;
+                }
+            }
+        });
+        band
+    }
+}
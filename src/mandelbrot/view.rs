@@ -0,0 +1,225 @@
+extern crate dashu_float;
+
+use dashu_float::DBig;
+
+use super::perturbation::{BigFloat, big_float};
+
+use std::{fmt, str::FromStr};
+
+// Decimal digits kept on top of what is needed to resolve the radius, covers the pixels across the view
+const PRECISION_MARGIN: usize = 20;
+
+/// Center of the view as exact decimals and the distance from it to the view's edge.
+/// Every backend converts the center to whatever precision it works in.
+/// Text form is `<re> <im> <radius>` and parses back to the exact same view.
+#[derive(Clone, PartialEq)]
+pub struct View {
+    center:     (DBig, DBig),
+    center_f64: (f64, f64),
+    radius:     f64,
+    margin:     usize,
+}
+
+impl View {
+    pub fn new(center: (f64, f64), radius: f64) -> Self {
+        let mut view = Self {
+            center: (DBig::ZERO, DBig::ZERO),
+            center_f64: (0., 0.),
+            radius,
+            margin: PRECISION_MARGIN,
+        };
+        view.grow_precision();
+        view.translate(center.0, center.1);
+        view
+    }
+
+    /// Center rounded to f64, for backends which don't need more
+    pub fn center_f64(&self) -> (f64, f64) {
+        self.center_f64
+    }
+
+    /// Center rounded to the given number of bits
+    pub fn center_binary(&self, bits: usize) -> (BigFloat, BigFloat) {
+        (
+            self.center.0.clone().with_base_and_precision::<2>(bits).value(),
+            self.center.1.clone().with_base_and_precision::<2>(bits).value(),
+        )
+    }
+
+    /// Center rounded to `digits` significant decimal digits, for showing it
+    pub fn center_text(&self, digits: usize) -> (String, String) {
+        (
+            self.center.0.clone().with_precision(digits).value().to_string(),
+            self.center.1.clone().with_precision(digits).value().to_string(),
+        )
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    /// Decimal digits the center needs at the current radius
+    pub fn precision(&self) -> usize {
+        (-self.radius.log10()).ceil().max(0.) as usize + self.margin
+    }
+
+    /// Same as `precision` but in bits
+    pub fn binary_precision(&self) -> usize {
+        (self.precision() as f64 * std::f64::consts::LOG2_10).ceil() as usize
+    }
+
+    /// Sets how many decimal digits beyond the radius are kept
+    pub fn set_precision_margin(&mut self, margin: usize) {
+        self.margin = margin;
+        self.grow_precision();
+    }
+
+    pub fn set_center(&mut self, center: (DBig, DBig)) {
+        self.center = center;
+        self.grow_precision();
+        self.update_center_f64();
+    }
+
+    pub fn set_radius(&mut self, radius: f64) {
+        self.radius = radius;
+        self.grow_precision();
+    }
+
+    pub fn zoom(&mut self, factor: f64) {
+        self.set_radius(self.radius*factor);
+    }
+
+    /// Moves the center by an offset small enough for f64
+    pub fn translate(&mut self, dx: f64, dy: f64) {
+        let digits = self.precision();
+        let bits   = self.binary_precision();
+        self.center.0 = &self.center.0 + big_float(dx, bits).with_base_and_precision::<10>(digits).value();
+        self.center.1 = &self.center.1 + big_float(dy, bits).with_base_and_precision::<10>(digits).value();
+        self.update_center_f64();
+    }
+
+    // Digits are never dropped when zooming out, so the text form stays lossless
+    fn grow_precision(&mut self) {
+        let digits = self.precision();
+        if self.center.0.precision() < digits {
+            self.center.0 = self.center.0.clone().with_precision(digits).value();
+        }
+        if self.center.1.precision() < digits {
+            self.center.1 = self.center.1.clone().with_precision(digits).value();
+        }
+    }
+
+    fn update_center_f64(&mut self) {
+        // Decimals have to be rounded in base 2 first, DBig::to_f64 only takes what fits exactly
+        self.center_f64 = (
+            self.center.0.clone().with_base_and_precision::<2>(53).value().to_f64().value(),
+            self.center.1.clone().with_base_and_precision::<2>(53).value().to_f64().value(),
+        );
+    }
+}
+
+/// Rotation and shear of the image around the view's center,
+/// maps offsets in pixel space onto offsets in the complex plane
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    /// Counterclockwise, in radians
+    pub angle:   f64,
+    /// Horizontal shear, x moves by `skew` for every unit of y
+    pub skew:    f64,
+    /// Horizontal scale on top of the view's radius
+    pub stretch: f64,
+}
+
+impl Transform {
+    /// Row major 2x2 matrix, the rotation applied after stretch and skew
+    pub fn matrix(&self) -> [f64;4] {
+        let (sin, cos) = self.angle.sin_cos();
+        [
+            cos*self.stretch, cos*self.skew - sin,
+            sin*self.stretch, sin*self.skew + cos,
+        ]
+    }
+
+    pub fn apply(&self, offset: (f64,f64)) -> (f64,f64) {
+        let m = self.matrix();
+        (m[0]*offset.0 + m[1]*offset.1, m[2]*offset.0 + m[3]*offset.1)
+    }
+
+    /// Maps an offset in the complex plane back into pixel space
+    pub fn invert(&self, offset: (f64,f64)) -> (f64,f64) {
+        let m   = self.matrix();
+        let det = m[0]*m[3] - m[1]*m[2];
+        ((m[3]*offset.0 - m[1]*offset.1) / det, (m[0]*offset.1 - m[2]*offset.0) / det)
+    }
+
+    pub fn rotate(&mut self, angle: f64) {
+        self.angle = (self.angle + angle) % std::f64::consts::TAU;
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            angle:   0.,
+            skew:    0.,
+            stretch: 1.,
+        }
+    }
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self::new((0., 0.), 2.)
+    }
+}
+
+impl fmt::Display for View {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {:e}", self.center.0, self.center.1, self.radius)
+    }
+}
+
+impl fmt::Debug for View {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "View({})", self)
+    }
+}
+
+impl FromStr for View {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split_whitespace().collect::<Vec<&str>>();
+        if parts.len() != 3 {
+            return Err(format!("Expected '<re> <im> <radius>', got '{}'", s));
+        }
+
+        let radius = parts[2].parse::<f64>().map_err(|e| e.to_string())?;
+        if !(radius.is_finite() && radius > 0.) {
+            return Err(format!("Invalid radius '{}'", parts[2]));
+        }
+
+        let re = DBig::from_str(parts[0]).map_err(|e| e.to_string())?;
+        let im = DBig::from_str(parts[1]).map_err(|e| e.to_string())?;
+
+        let mut view = Self::new((0., 0.), radius);
+        view.set_center((re, im));
+        Ok(view)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_dyadic_center() {
+        let view = "-1.25066 0.02012 1e-27".parse::<View>().unwrap();
+        assert_eq!(view.center_f64(), (-1.25066, 0.02012));
+        assert_eq!(view.to_string().parse::<View>().unwrap(), view);
+
+        let mut view = View::new((0., 0.), 1e-27);
+        view.set_center(("0".parse().unwrap(), "0.02012".parse().unwrap()));
+        assert_eq!(view.center_f64(), (0., 0.02012));
+    }
+}